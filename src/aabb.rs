@@ -0,0 +1,114 @@
+use super::Ray;
+use cgmath::Vector3;
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb<T> {
+    min: Vector3<T>,
+    max: Vector3<T>,
+}
+
+impl<T: cgmath::BaseFloat> Aabb<T> {
+    pub fn new(min: Vector3<T>, max: Vector3<T>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn min(&self) -> &Vector3<T> {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Vector3<T> {
+        &self.max
+    }
+
+    pub fn from_points<I: IntoIterator<Item = Vector3<T>>>(points: I) -> Option<Self> {
+        let mut iter = points.into_iter();
+        let first = iter.next()?;
+        Some(iter.fold(Self::new(first, first), |acc, p| {
+            acc.surrounding(&Self::new(p, p))
+        }))
+    }
+
+    pub fn surrounding(&self, other: &Self) -> Self {
+        Self {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Vector3<T> {
+        (self.min + self.max) / (T::one() + T::one())
+    }
+
+    pub fn extent(&self) -> Vector3<T> {
+        self.max - self.min
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.extent();
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn contains(&self, p: &Vector3<T>) -> bool {
+        (0..3).all(|axis| p[axis] >= self.min[axis] && p[axis] <= self.max[axis])
+    }
+
+    pub fn surface_area(&self) -> T {
+        let d = self.max - self.min;
+        (d.x * d.y + d.y * d.z + d.z * d.x) * (T::one() + T::one())
+    }
+
+    pub fn hit(&self, r: &Ray<T>, t: Range<T>) -> bool {
+        self.intersect(r, t).is_some()
+    }
+
+    // the part of `t` for which the ray is inside the box, using the slab test which relies
+    // on IEEE infinities when a direction component is zero
+    pub fn intersect(&self, r: &Ray<T>, t: Range<T>) -> Option<Range<T>> {
+        let mut t_min = t.start;
+        let mut t_max = t.end;
+        for axis in 0..3 {
+            let inv_d = T::one() / r.direction()[axis];
+            let mut t0 = (self.min[axis] - r.origin()[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin()[axis]) * inv_d;
+            if inv_d < T::zero() {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some(t_min..t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+
+    #[test]
+    fn rays_hit_boxes_with_no_thickness() {
+        let flat = Aabb::new(vec3(-1.0, 0.0, -1.0), vec3(1.0, 0.0, 1.0));
+        let down = Ray::new(vec3(0.2, 1.0, 0.3), vec3(0.0, -1.0, 0.0));
+        assert!(flat.hit(&down, 0.001..std::f64::MAX));
+        let beside = Ray::new(vec3(2.0, 1.0, 0.3), vec3(0.0, -1.0, 0.0));
+        assert!(!flat.hit(&beside, 0.001..std::f64::MAX));
+    }
+}
@@ -0,0 +1,287 @@
+use super::{Aabb, HitRecord, HitTable, HitTableList, Ray};
+use cgmath::Vector3;
+use std::ops::Range;
+
+const BUCKET_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+
+enum Node<T> {
+    Leaf {
+        bounds: Aabb<T>,
+        first: usize,
+        count: usize,
+    },
+    // the first child always directly follows its parent in `nodes`
+    Interior {
+        bounds: Aabb<T>,
+        second_child: usize,
+        axis: usize,
+    },
+}
+
+struct Primitive<T> {
+    object: Box<dyn HitTable<T>>,
+    bounds: Aabb<T>,
+    centroid: Vector3<T>,
+}
+
+pub struct Bvh<T> {
+    nodes: Vec<Node<T>>,
+    objects: Vec<Box<dyn HitTable<T>>>,
+    unbounded: Vec<Box<dyn HitTable<T>>>,
+}
+
+fn partition<P, F: Fn(&P) -> bool>(items: &mut [P], pred: F) -> usize {
+    let mut i = 0;
+    for j in 0..items.len() {
+        if pred(&items[j]) {
+            items.swap(i, j);
+            i += 1;
+        }
+    }
+    i
+}
+
+impl<T: cgmath::BaseFloat> Bvh<T> {
+    pub fn new(list: Vec<Box<dyn HitTable<T>>>) -> Self {
+        let mut primitives = Vec::with_capacity(list.len());
+        let mut unbounded = vec![];
+        for object in list {
+            match object.bounding_box() {
+                Some(bounds) => primitives.push(Primitive {
+                    centroid: bounds.centroid(),
+                    bounds,
+                    object,
+                }),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut nodes = Vec::with_capacity(primitives.len() * 2);
+        if !primitives.is_empty() {
+            Self::build(&mut nodes, &mut primitives, 0);
+        }
+
+        Self {
+            nodes,
+            objects: primitives.into_iter().map(|p| p.object).collect(),
+            unbounded,
+        }
+    }
+
+    fn build(nodes: &mut Vec<Node<T>>, primitives: &mut [Primitive<T>], offset: usize) {
+        let bounds = primitives
+            .iter()
+            .skip(1)
+            .fold(primitives[0].bounds, |acc, p| acc.surrounding(&p.bounds));
+        let leaf = Node::Leaf {
+            bounds,
+            first: offset,
+            count: primitives.len(),
+        };
+        if primitives.len() == 1 {
+            nodes.push(leaf);
+            return;
+        }
+
+        let centroid_bounds = Aabb::from_points(primitives.iter().map(|p| p.centroid)).unwrap();
+        let extent = centroid_bounds.extent();
+        let axis = centroid_bounds.longest_axis();
+        if extent[axis] <= T::zero() {
+            nodes.push(leaf);
+            return;
+        }
+
+        let bucket_count = T::from(BUCKET_COUNT).unwrap();
+        let cmin = centroid_bounds.min()[axis];
+        let bucket_of = |p: &Primitive<T>| {
+            let b = ((p.centroid[axis] - cmin) / extent[axis] * bucket_count)
+                .to_usize()
+                .unwrap_or(0);
+            b.min(BUCKET_COUNT - 1)
+        };
+
+        let mut buckets: [Option<(usize, Aabb<T>)>; BUCKET_COUNT] = [None; BUCKET_COUNT];
+        for p in primitives.iter() {
+            let b = &mut buckets[bucket_of(p)];
+            *b = Some(match b {
+                Some((count, bounds)) => (*count + 1, bounds.surrounding(&p.bounds)),
+                None => (1, p.bounds),
+            });
+        }
+
+        // surface area heuristic, with traversal cost relative to an intersection test
+        let traversal_cost = T::from(0.125).unwrap();
+        let mut best: Option<(usize, T)> = None;
+        for split in 1..BUCKET_COUNT {
+            let side = |range: Range<usize>| {
+                buckets[range].iter().flatten().fold(
+                    None,
+                    |acc: Option<(usize, Aabb<T>)>, (count, b)| {
+                        Some(match acc {
+                            Some((c, a)) => (c + count, a.surrounding(b)),
+                            None => (*count, *b),
+                        })
+                    },
+                )
+            };
+            if let (Some((c0, b0)), Some((c1, b1))) = (side(0..split), side(split..BUCKET_COUNT)) {
+                let cost = traversal_cost
+                    + (T::from(c0).unwrap() * b0.surface_area()
+                        + T::from(c1).unwrap() * b1.surface_area())
+                        / bounds.surface_area();
+                match best {
+                    Some((_, c)) if c <= cost => (),
+                    _ => best = Some((split, cost)),
+                }
+            }
+        }
+
+        let leaf_cost = T::from(primitives.len()).unwrap();
+        let split = match best {
+            Some((split, cost)) if primitives.len() > MAX_LEAF_SIZE || cost < leaf_cost => split,
+            _ => {
+                nodes.push(leaf);
+                return;
+            }
+        };
+
+        let mid = partition(primitives, |p| bucket_of(p) < split);
+        let index = nodes.len();
+        nodes.push(Node::Interior {
+            bounds,
+            second_child: 0,
+            axis,
+        });
+        let (left, right) = primitives.split_at_mut(mid);
+        Self::build(nodes, left, offset);
+        let second = nodes.len();
+        if let Node::Interior { second_child, .. } = &mut nodes[index] {
+            *second_child = second;
+        }
+        Self::build(nodes, right, offset + mid);
+    }
+}
+
+impl<T: cgmath::BaseFloat> From<HitTableList<T>> for Bvh<T> {
+    fn from(list: HitTableList<T>) -> Self {
+        Self::new(list.into_vec())
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Bvh<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let mut closest_so_far = t.end;
+        let mut hit = None;
+        for ht in self.unbounded.iter() {
+            if let Some(hc) = ht.hit(r, t.start..closest_so_far) {
+                closest_so_far = hc.get_t();
+                hit = Some(hc);
+            }
+        }
+        if self.nodes.is_empty() {
+            return hit;
+        }
+
+        let mut stack = Vec::with_capacity(32);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            match &self.nodes[index] {
+                Node::Leaf {
+                    bounds,
+                    first,
+                    count,
+                } => {
+                    if bounds.hit(r, t.start..closest_so_far) {
+                        for ht in self.objects[*first..*first + *count].iter() {
+                            if let Some(hc) = ht.hit(r, t.start..closest_so_far) {
+                                closest_so_far = hc.get_t();
+                                hit = Some(hc);
+                            }
+                        }
+                    }
+                }
+                Node::Interior {
+                    bounds,
+                    second_child,
+                    axis,
+                } => {
+                    if bounds.hit(r, t.start..closest_so_far) {
+                        // visit the near child first so the far one is more likely to be culled
+                        if r.direction()[*axis] < T::zero() {
+                            stack.push(index + 1);
+                            stack.push(*second_child);
+                        } else {
+                            stack.push(*second_child);
+                            stack.push(index + 1);
+                        }
+                    }
+                }
+            }
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| match node {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => *bounds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Lambertian, Material, Sphere};
+    use cgmath::{vec3, InnerSpace};
+    use rand::prelude::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn bvh_finds_the_same_closest_hits_as_a_list() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut list = HitTableList::new();
+        let mut objects: Vec<Box<dyn HitTable<f64>>> = vec![];
+        for _ in 0..200 {
+            let center = vec3(
+                rng.gen_range(-10.0, 10.0),
+                rng.gen_range(-10.0, 10.0),
+                rng.gen_range(-10.0, 10.0),
+            );
+            let radius = rng.gen_range(0.1, 1.5);
+            // every sphere gets its own material so hits can be told apart
+            let material: Rc<dyn Material<f64>> = Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+            list.add(Box::new(Sphere::new(center, radius, Rc::clone(&material))));
+            objects.push(Box::new(Sphere::new(center, radius, material)));
+        }
+        let bvh = Bvh::new(objects);
+
+        for _ in 0..2000 {
+            let origin = vec3(
+                rng.gen_range(-15.0, 15.0),
+                rng.gen_range(-15.0, 15.0),
+                rng.gen_range(-15.0, 15.0),
+            );
+            let target = vec3(
+                rng.gen_range(-10.0, 10.0),
+                rng.gen_range(-10.0, 10.0),
+                rng.gen_range(-10.0, 10.0),
+            );
+            let r = Ray::new(origin, (target - origin).normalize());
+            match (
+                list.hit(&r, 0.001..std::f64::MAX),
+                bvh.hit(&r, 0.001..std::f64::MAX),
+            ) {
+                (None, None) => (),
+                (Some(a), Some(b)) => {
+                    assert_eq!(a.get_t(), b.get_t());
+                    assert!(Rc::ptr_eq(a.get_material(), b.get_material()));
+                }
+                (a, b) => panic!("list hit {}, bvh hit {}", a.is_some(), b.is_some()),
+            }
+        }
+    }
+}
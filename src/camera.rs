@@ -14,6 +14,8 @@ pub struct Camera<T> {
     v: Vector3<T>,
     w: Vector3<T>,
     lens_radius: T,
+    time0: T,
+    time1: T,
 }
 
 impl Camera<f64> {
@@ -46,6 +48,8 @@ impl Camera<f64> {
             v,
             w,
             lens_radius: aperture / 2.0,
+            time0: 0.0,
+            time1: 0.0,
         }
     }
 }
@@ -73,14 +77,46 @@ where
     T: cgmath::BaseFloat,
     Standard: Distribution<T>,
 {
+    // rays are spread uniformly over the time the shutter is open
+    pub fn set_shutter(&mut self, open: T, close: T) {
+        self.time0 = open;
+        self.time1 = close;
+    }
+
     pub fn ray(&self, u: T, v: T) -> Ray<T> {
         use cgmath::ElementWise;
 
         let rd = rand_in_unit_disk().mul_element_wise(self.lens_radius);
         let offset = self.u.mul_element_wise(rd.x) + self.v.mul_element_wise(rd.y);
-        Ray::new(
+        let time = self.time0 + rand::thread_rng().gen::<T>() * (self.time1 - self.time0);
+        Ray::with_time(
             self.origin + offset,
             self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin - offset,
+            time,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_times_stay_inside_the_shutter() {
+        let mut camera = Camera::new(
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 0.0, -1.0),
+            vec3(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.1,
+            1.0,
+        );
+        assert_eq!(camera.ray(0.5, 0.5).time(), 0.0);
+        camera.set_shutter(0.25, 0.75);
+        let times: Vec<f64> = (0..1000).map(|_| camera.ray(0.3, 0.6).time()).collect();
+        assert!(times.iter().all(|t| *t >= 0.25 && *t <= 0.75));
+        // spread over the whole interval rather than stuck at one end
+        assert!(times.iter().any(|t| *t < 0.3) && times.iter().any(|t| *t > 0.7));
+    }
+}
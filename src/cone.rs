@@ -0,0 +1,198 @@
+use super::csg::{convex_span, Solid, Span};
+use super::cylinder::{solve_quadratic, sort_crossings};
+use super::plane::turns;
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::{vec3, InnerSpace, Vector2, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+// a closed cone standing on the disk at `base` with its apex `height` above it along +y.
+// u goes around the axis, v runs from base to apex on the side and outwards on the base
+pub struct Cone<T> {
+    base: Vector3<T>,
+    radius: T,
+    height: T,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T> Cone<T> {
+    pub fn new(base: Vector3<T>, radius: T, height: T, material: Rc<dyn Material<T>>) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            material,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Cone<T> {
+    // every crossing of the surface along the line of the ray, in order, tagged with the part
+    // it's on: 0 is the side, 1 the base
+    fn crossings(&self, r: &Ray<T>) -> Vec<(T, usize)> {
+        let o = r.origin() - self.base;
+        let d = *r.direction();
+        // x^2 + z^2 = (k (height - y))^2, with k the radius shrinking per unit of height
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let h = self.height - o.y;
+        let mut crossings = Vec::with_capacity(2);
+
+        if let Some((t0, t1)) = solve_quadratic(
+            d.x * d.x + d.z * d.z - k2 * d.y * d.y,
+            o.x * d.x + o.z * d.z + k2 * h * d.y,
+            o.x * o.x + o.z * o.z - k2 * h * h,
+        ) {
+            for &temp in &[t0, t1] {
+                let y = o.y + temp * d.y;
+                if y >= T::zero() && y <= self.height {
+                    crossings.push((temp, 0));
+                }
+            }
+        }
+        if d.y != T::zero() {
+            let temp = -o.y / d.y;
+            let (x, z) = (o.x + temp * d.x, o.z + temp * d.z);
+            if x * x + z * z <= self.radius * self.radius {
+                crossings.push((temp, 1));
+            }
+        }
+        sort_crossings(&mut crossings, |c| c.0);
+        crossings
+    }
+
+    fn hit_record(&self, r: &Ray<T>, temp: T, part: usize) -> HitRecord<T> {
+        let p = r.point_at_parameter(temp);
+        let local = p - self.base;
+        let u = turns(local.z, local.x);
+        let (normal, v, dpdv) = if part == 0 {
+            // the slope is the same all the way up, so the normal only depends on the angle
+            let around = vec3(local.x, T::zero(), local.z);
+            let around = if around.magnitude2() > T::zero() {
+                around.normalize()
+            } else {
+                vec3(T::one(), T::zero(), T::zero())
+            };
+            (
+                vec3(around.x, self.radius / self.height, around.z).normalize(),
+                local.y / self.height,
+                vec3(
+                    -around.x * self.radius,
+                    self.height,
+                    -around.z * self.radius,
+                ),
+            )
+        } else {
+            (
+                vec3(T::zero(), -T::one(), T::zero()),
+                (local.x * local.x + local.z * local.z).sqrt() / self.radius,
+                vec3(local.x, T::zero(), local.z),
+            )
+        };
+        let mut rec = HitRecord::new(r, temp, p, normal, Rc::clone(&self.material));
+        rec.set_uv(Vector2::new(u, v));
+        rec.set_tangents(vec3(-local.z, T::zero(), local.x), dpdv);
+        rec
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Cone<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        self.crossings(r)
+            .into_iter()
+            .find(|(temp, _)| *temp > t.start && *temp < t.end)
+            .map(|(temp, part)| self.hit_record(r, temp, part))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let r = self.radius.abs();
+        Some(Aabb::new(
+            self.base - vec3(r, T::zero(), r),
+            self.base + vec3(r, self.height, r),
+        ))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Solid<T> for Cone<T> {
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>> {
+        convex_span(
+            self.crossings(r)
+                .into_iter()
+                .map(|(temp, part)| self.hit_record(r, temp, part))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use std::f64::consts::PI;
+
+    fn cone() -> Cone<f64> {
+        Cone::new(
+            vec3(-1.0, 0.5, 0.0),
+            1.0,
+            2.0,
+            Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn side_hits_sampled_points() {
+        let cone = cone();
+        for i in 0..16 {
+            let u = (f64::from(i) + 0.5) / 16.0;
+            let v = f64::from(i) / 15.0 * 0.9 + 0.05;
+            let phi = u * 2.0 * PI;
+            let around = vec3(phi.cos(), 0.0, phi.sin());
+            let target = vec3(-1.0, 0.5, 0.0) + around * (1.0 - v) + vec3(0.0, 2.0 * v, 0.0);
+            // the surface rises 2 for every 1 it moves in, so the normal leans out by 1/2
+            let normal = vec3(around.x, 0.5, around.z).normalize();
+            let r = Ray::new(target + normal * 3.0, -normal);
+            let rec = cone.hit(&r, 0.0..f64::MAX).unwrap();
+            assert!((rec.get_t() - 3.0).abs() < 1e-9);
+            assert!((rec.get_p() - target).magnitude() < 1e-9);
+            assert!((rec.get_normal() - normal).magnitude() < 1e-9);
+            assert!((rec.get_uv() - Vector2::new(u, v)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn base_hits_sampled_points() {
+        let cone = cone();
+        for i in 0..16 {
+            let u = (f64::from(i) + 0.5) / 16.0;
+            let v = f64::from(i) / 16.0 * 0.9 + 0.05;
+            let phi = u * 2.0 * PI;
+            let target = vec3(-1.0, 0.5, 0.0) + vec3(phi.cos(), 0.0, phi.sin()) * v;
+            let r = Ray::new(target - vec3(0.0, 4.0, 0.0), vec3(0.0, 1.0, 0.0));
+            let rec = cone.hit(&r, 0.0..f64::MAX).unwrap();
+            assert!((rec.get_t() - 4.0).abs() < 1e-9);
+            assert!((rec.get_normal() - vec3(0.0, -1.0, 0.0)).magnitude() < 1e-9);
+            assert!((rec.get_uv() - Vector2::new(u, v)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn ignores_the_mirrored_nappe() {
+        let cone = cone();
+        // passes through the reflected cone above the apex but not through the real one
+        let r = Ray::new(vec3(-3.0, 3.5, 0.0), vec3(1.0, 0.0, 0.0));
+        assert!(cone.hit(&r, 0.0..f64::MAX).is_none());
+    }
+
+    #[test]
+    fn rays_along_the_axis_enter_at_the_apex() {
+        let cone = cone();
+        // the side's two roots meet at the apex
+        let down = Ray::new(vec3(-1.0, 5.0, 0.0), vec3(0.0, -1.0, 0.0));
+        let rec = cone.hit(&down, 0.0..f64::MAX).unwrap();
+        assert!((rec.get_t() - 2.5).abs() < 1e-9);
+        let spans = cone.spans(&down);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].exit.get_t() - 4.5).abs() < 1e-9);
+        assert!((spans[0].exit.get_normal() - vec3(0.0, 1.0, 0.0)).magnitude() < 1e-9);
+    }
+}
@@ -0,0 +1,153 @@
+use super::{Aabb, HitRecord, HitTable, Isotropic, Material, Ray};
+use cgmath::{vec3, InnerSpace, Vector3};
+use rand::distributions::Standard;
+use rand::prelude::*;
+use std::ops::Range;
+use std::rc::Rc;
+
+// a volume of uniform density filling a closed boundary, rays scatter inside it with a
+// probability that grows with the distance they travel through it
+pub struct ConstantMedium<T> {
+    boundary: Box<dyn HitTable<T>>,
+    neg_inv_density: T,
+    phase_function: Rc<dyn Material<T>>,
+}
+
+impl<T> ConstantMedium<T>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+{
+    pub fn new(boundary: Box<dyn HitTable<T>>, density: T, albedo: Vector3<T>) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -T::one() / density,
+            phase_function: Rc::new(Isotropic::new(albedo)),
+        }
+    }
+}
+
+impl<T> HitTable<T> for ConstantMedium<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        // the boundary is crossed twice even when the ray starts inside of it
+        let enter = self
+            .boundary
+            .hit(r, T::neg_infinity()..T::infinity())?
+            .get_t();
+        let exit = self
+            .boundary
+            .hit(r, enter + T::from(0.0001).unwrap()..T::infinity())?
+            .get_t();
+
+        let enter = enter.max(t.start).max(T::zero());
+        let exit = exit.min(t.end);
+        if enter >= exit {
+            return None;
+        }
+
+        let ray_length = r.direction().magnitude();
+        let distance_inside_boundary = (exit - enter) * ray_length;
+        let hit_distance = self.neg_inv_density * thread_rng().gen::<T>().ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let temp = enter + hit_distance / ray_length;
+        // scattering is isotropic, so the normal is arbitrary
+        Some(HitRecord::new(
+            r,
+            temp,
+            r.point_at_parameter(temp),
+            vec3(T::one(), T::zero(), T::zero()),
+            Rc::clone(&self.phase_function),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BoxShape, Lambertian, Sphere};
+
+    fn scattered(medium: &ConstantMedium<f64>, r: &Ray<f64>) -> f64 {
+        let n = 20_000;
+        let hits = (0..n)
+            .filter(|_| medium.hit(r, 0.001..f64::MAX).is_some())
+            .count();
+        hits as f64 / f64::from(n)
+    }
+
+    #[test]
+    fn scattering_follows_beers_law() {
+        let wall = Lambertian::new(vec3(0.5, 0.5, 0.5));
+        // a slab one unit thick along x
+        let boundary = BoxShape::new(vec3(0.0, -5.0, -5.0), vec3(1.0, 5.0, 5.0), Rc::new(wall));
+        let medium = ConstantMedium::new(Box::new(boundary), 0.7, vec3(1.0, 1.0, 1.0));
+        // the length of the direction doesn't matter
+        let r = Ray::new(vec3(-2.0, 0.0, 0.0), vec3(3.0, 0.0, 0.0));
+        let expected = 1.0 - (-0.7f64).exp();
+        let fraction = scattered(&medium, &r);
+        assert!((fraction - expected).abs() < 0.02, "{}", fraction);
+
+        // scattering happens inside the slab
+        for _ in 0..100 {
+            if let Some(rec) = medium.hit(&r, 0.001..f64::MAX) {
+                assert!(rec.get_p().x >= 0.0 && rec.get_p().x <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn rays_starting_inside_only_cross_whats_ahead() {
+        let material = Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let boundary = Sphere::new(vec3(0.0, 0.0, 0.0), 2.0, material);
+        let medium = ConstantMedium::new(Box::new(boundary), 0.5, vec3(1.0, 1.0, 1.0));
+        // from the center there are two units of fog left to cross
+        let r = Ray::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+        let expected = 1.0 - (-1.0f64).exp();
+        let fraction = scattered(&medium, &r);
+        assert!((fraction - expected).abs() < 0.02, "{}", fraction);
+        for _ in 0..100 {
+            if let Some(rec) = medium.hit(&r, 0.001..f64::MAX) {
+                assert!(rec.get_t() > 0.0 && rec.get_t() < 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn isotropic_scattering_is_uniform() {
+        let phase: Rc<dyn Material<f64>> = Rc::new(Isotropic::new(vec3(0.2, 0.4, 0.6)));
+        let r = Ray::new(vec3(0.0, 0.0, -1.0), vec3(0.0, 0.0, 1.0));
+        let rec = HitRecord::new(
+            &r,
+            1.0,
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            Rc::clone(&phase),
+        );
+        let density = 1.0 / (4.0 * std::f64::consts::PI);
+        let mut mean = vec3(0.0, 0.0, 0.0);
+        let n = 10_000;
+        for _ in 0..n {
+            let scatter = phase.scatter(&r, &rec).unwrap();
+            assert_eq!(scatter.attenuation, vec3(0.2, 0.4, 0.6));
+            assert_eq!(scatter.pdf, Some(density));
+            assert_eq!(*scatter.ray.origin(), vec3(0.0, 0.0, 0.0));
+            mean += scatter.ray.direction().normalize() / f64::from(n);
+        }
+        // no preferred direction, not even along the incoming ray
+        assert!(mean.magnitude() < 0.05, "{:?}", mean);
+        let direction = vec3(0.3, -0.9, 0.1);
+        assert_eq!(phase.pdf(&r, &rec, &direction), density);
+        let bsdf = phase.eval(&r, &rec, &direction).unwrap();
+        assert!((bsdf - vec3(0.2, 0.4, 0.6) * density).magnitude() < 1e-12);
+    }
+}
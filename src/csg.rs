@@ -0,0 +1,252 @@
+use super::{Aabb, HitRecord, HitTable, Ray};
+use std::ops::Range;
+
+// the stretch of a ray spent inside a solid, from where it enters to where it leaves
+pub struct Span<T> {
+    pub enter: HitRecord<T>,
+    pub exit: HitRecord<T>,
+}
+
+// a closed shape with an inside, so it can take part in csg
+pub trait Solid<T>: HitTable<T> {
+    // every span along the whole line of the ray, including those behind its origin,
+    // ordered and not overlapping
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>>;
+}
+
+// turns the ordered crossings of a convex solid into at most one span
+pub(crate) fn convex_span<T>(mut crossings: Vec<HitRecord<T>>) -> Vec<Span<T>> {
+    if crossings.len() < 2 {
+        return Vec::new();
+    }
+    let exit = crossings.pop().unwrap();
+    let enter = crossings.swap_remove(0);
+    vec![Span { enter, exit }]
+}
+
+// the nearest span boundary inside `t`
+pub(crate) fn first_hit<T: cgmath::BaseFloat>(
+    spans: Vec<Span<T>>,
+    t: &Range<T>,
+) -> Option<HitRecord<T>> {
+    spans
+        .into_iter()
+        .flat_map(|span| vec![span.enter, span.exit])
+        .find(|rec| rec.get_t() > t.start && rec.get_t() < t.end)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(self, left: bool, right: bool) -> bool {
+        match self {
+            CsgOp::Union => left || right,
+            CsgOp::Intersection => left && right,
+            CsgOp::Difference => left && !right,
+        }
+    }
+}
+
+pub struct Csg<T> {
+    op: CsgOp,
+    left: Box<dyn Solid<T>>,
+    right: Box<dyn Solid<T>>,
+}
+
+impl<T> Csg<T> {
+    pub fn new(op: CsgOp, left: Box<dyn Solid<T>>, right: Box<dyn Solid<T>>) -> Self {
+        Self { op, left, right }
+    }
+
+    pub fn union(left: Box<dyn Solid<T>>, right: Box<dyn Solid<T>>) -> Self {
+        Self::new(CsgOp::Union, left, right)
+    }
+
+    pub fn intersection(left: Box<dyn Solid<T>>, right: Box<dyn Solid<T>>) -> Self {
+        Self::new(CsgOp::Intersection, left, right)
+    }
+
+    // carves `right` out of `left`
+    pub fn difference(left: Box<dyn Solid<T>>, right: Box<dyn Solid<T>>) -> Self {
+        Self::new(CsgOp::Difference, left, right)
+    }
+}
+
+impl<T: cgmath::BaseFloat> Solid<T> for Csg<T> {
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>> {
+        // walk both solids' boundaries in order, tracking which of them the ray is in
+        let mut events = Vec::new();
+        for (is_left, spans) in [(true, self.left.spans(r)), (false, self.right.spans(r))] {
+            // a span with a NaN end can't be ordered, and degenerate rays produce them
+            for span in spans
+                .into_iter()
+                .filter(|s| !s.enter.get_t().is_nan() && !s.exit.get_t().is_nan())
+            {
+                events.push((is_left, true, span.enter));
+                events.push((is_left, false, span.exit));
+            }
+        }
+        events.sort_by(|a, b| a.2.get_t().partial_cmp(&b.2.get_t()).unwrap());
+
+        let (mut in_left, mut in_right) = (false, false);
+        let mut enter = None;
+        let mut spans = Vec::new();
+        for (is_left, entering, mut rec) in events {
+            let was_inside = self.op.inside(in_left, in_right);
+            if is_left {
+                in_left = entering;
+            } else {
+                in_right = entering;
+            }
+            let inside = self.op.inside(in_left, in_right);
+            if inside == was_inside {
+                continue;
+            }
+            // the carved out solid's inside is the result's outside
+            if !is_left && self.op == CsgOp::Difference {
+                let front_face = rec.get_front_face();
+                rec.set_front_face(!front_face);
+            }
+            if inside {
+                enter = Some(rec);
+            } else if let Some(enter) = enter.take() {
+                spans.push(Span { enter, exit: rec });
+            }
+        }
+        spans
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Csg<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        first_hit(self.spans(r), &t)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        match self.op {
+            CsgOp::Union => {
+                let left = self.left.bounding_box()?;
+                Some(left.surrounding(&self.right.bounding_box()?))
+            }
+            CsgOp::Intersection => self
+                .left
+                .bounding_box()
+                .or_else(|| self.right.bounding_box()),
+            CsgOp::Difference => self.left.bounding_box(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cylinder, Lambertian, Material, Sphere};
+    use cgmath::{vec3, InnerSpace};
+    use std::rc::Rc;
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    // a unit sphere at the origin with a hole of radius 0.25 drilled down the y axis
+    fn drilled() -> Csg<f64> {
+        Csg::difference(
+            Box::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material())),
+            Box::new(Cylinder::new(vec3(0.0, -2.0, 0.0), 0.25, 4.0, material())),
+        )
+    }
+
+    #[test]
+    fn difference_carves_a_hole() {
+        let csg = drilled();
+        let down = Ray::new(vec3(0.0, 5.0, 0.0), vec3(0.0, -1.0, 0.0));
+        assert!(csg.hit(&down, 0.0..f64::MAX).is_none());
+
+        let across = Ray::new(vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        let spans = csg.spans(&across);
+        assert_eq!(spans.len(), 2);
+        assert!((spans[0].enter.get_t() - 4.0).abs() < 1e-9);
+        assert!((spans[0].exit.get_t() - 4.75).abs() < 1e-9);
+        assert!((spans[1].enter.get_t() - 5.25).abs() < 1e-9);
+        assert!((spans[1].exit.get_t() - 6.0).abs() < 1e-9);
+        // the walls of the hole face into it
+        assert!(spans[0].enter.get_front_face());
+        assert!(!spans[0].exit.get_front_face());
+        assert!((spans[0].exit.get_normal() - vec3(-1.0, 0.0, 0.0)).magnitude() < 1e-9);
+        assert!(spans[1].enter.get_front_face());
+        assert!((spans[1].enter.get_normal() - vec3(-1.0, 0.0, 0.0)).magnitude() < 1e-9);
+        assert!(!spans[1].exit.get_front_face());
+
+        // starting inside the hole the nearest surface is its wall
+        let inside = Ray::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        let rec = csg.hit(&inside, 0.0..f64::MAX).unwrap();
+        assert!((rec.get_t() - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn union_and_intersection_of_overlapping_spheres() {
+        let sphere = |x| Box::new(Sphere::new(vec3(x, 0.0, 0.0), 1.0, material()));
+        let r = Ray::new(vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+
+        let union = Csg::union(sphere(-0.5), sphere(0.5));
+        let spans = union.spans(&r);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter.get_t() - 3.5).abs() < 1e-9);
+        assert!((spans[0].exit.get_t() - 6.5).abs() < 1e-9);
+
+        let intersection = Csg::intersection(sphere(-0.5), sphere(0.5));
+        let spans = intersection.spans(&r);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter.get_t() - 4.5).abs() < 1e-9);
+        assert!((spans[0].exit.get_t() - 5.5).abs() < 1e-9);
+
+        let apart = Csg::intersection(sphere(-2.0), sphere(2.0));
+        assert!(apart.hit(&r, 0.0..f64::MAX).is_none());
+    }
+
+    // a solid whose only span ends at NaN, as a degenerate ray can produce
+    struct Broken;
+
+    impl HitTable<f64> for Broken {
+        fn hit(&self, _r: &Ray<f64>, _t: Range<f64>) -> Option<HitRecord<f64>> {
+            None
+        }
+
+        fn bounding_box(&self) -> Option<Aabb<f64>> {
+            None
+        }
+    }
+
+    impl Solid<f64> for Broken {
+        fn spans(&self, r: &Ray<f64>) -> Vec<Span<f64>> {
+            let normal = vec3(1.0, 0.0, 0.0);
+            let rec = |t| HitRecord::new(r, t, r.point_at_parameter(t), normal, material());
+            vec![Span {
+                enter: rec(1.0),
+                exit: rec(std::f64::NAN),
+            }]
+        }
+    }
+
+    #[test]
+    fn nan_spans_are_dropped() {
+        let r = Ray::new(vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        let sphere = Box::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material()));
+        let union = Csg::union(sphere, Box::new(Broken));
+        let spans = union.spans(&r);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].enter.get_t() - 4.0).abs() < 1e-9);
+        assert!((spans[0].exit.get_t() - 6.0).abs() < 1e-9);
+
+        let sphere = Box::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material()));
+        let degenerate = Ray::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0));
+        assert!(Csg::difference(sphere, Box::new(Broken))
+            .hit(&degenerate, 0.001..f64::MAX)
+            .is_none());
+    }
+}
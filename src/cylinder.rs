@@ -0,0 +1,249 @@
+use super::csg::{convex_span, Solid, Span};
+use super::plane::turns;
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::{vec3, Vector2, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+// roots of a t^2 + 2 b t + c, smallest first
+pub(crate) fn solve_quadratic<T: cgmath::BaseFloat>(a: T, b: T, c: T) -> Option<(T, T)> {
+    if a == T::zero() {
+        if b == T::zero() {
+            return None;
+        }
+        let root = -c / (T::from(2.0).unwrap() * b);
+        return Some((root, root));
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < T::zero() {
+        return None;
+    }
+    let (r0, r1) = (
+        (-b - discriminant.sqrt()) / a,
+        (-b + discriminant.sqrt()) / a,
+    );
+    Some((r0.min(r1), r0.max(r1)))
+}
+
+// orders crossings along the ray by their `t`. degenerate rays can produce NaN roots, which are
+// no crossing at all and would stop the sort, so they're dropped
+pub(crate) fn sort_crossings<T, C, F>(crossings: &mut Vec<C>, t: F)
+where
+    T: cgmath::BaseFloat,
+    F: Fn(&C) -> T,
+{
+    crossings.retain(|c| !t(c).is_nan());
+    crossings.sort_by(|a, b| t(a).partial_cmp(&t(b)).unwrap());
+}
+
+// a closed cylinder standing on the disk at `base`, along +y. u goes around the axis,
+// v runs from bottom to top on the side and from the center outwards on the caps
+pub struct Cylinder<T> {
+    base: Vector3<T>,
+    radius: T,
+    height: T,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T> Cylinder<T> {
+    pub fn new(base: Vector3<T>, radius: T, height: T, material: Rc<dyn Material<T>>) -> Self {
+        Self {
+            base,
+            radius,
+            height,
+            material,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Cylinder<T> {
+    // every crossing of the surface along the line of the ray, in order, tagged with the part
+    // it's on: 0 is the side, 1 the bottom cap and 2 the top cap
+    fn crossings(&self, r: &Ray<T>) -> Vec<(T, usize)> {
+        let o = r.origin() - self.base;
+        let d = *r.direction();
+        let mut crossings = Vec::with_capacity(2);
+
+        if let Some((t0, t1)) = solve_quadratic(
+            d.x * d.x + d.z * d.z,
+            o.x * d.x + o.z * d.z,
+            o.x * o.x + o.z * o.z - self.radius * self.radius,
+        ) {
+            for &temp in &[t0, t1] {
+                let y = o.y + temp * d.y;
+                if y >= T::zero() && y <= self.height {
+                    crossings.push((temp, 0));
+                }
+            }
+        }
+        if d.y != T::zero() {
+            for &(part, y) in &[(1, T::zero()), (2, self.height)] {
+                let temp = (y - o.y) / d.y;
+                let (x, z) = (o.x + temp * d.x, o.z + temp * d.z);
+                if x * x + z * z <= self.radius * self.radius {
+                    crossings.push((temp, part));
+                }
+            }
+        }
+        sort_crossings(&mut crossings, |c| c.0);
+        crossings
+    }
+
+    fn hit_record(&self, r: &Ray<T>, temp: T, part: usize) -> HitRecord<T> {
+        let p = r.point_at_parameter(temp);
+        let local = p - self.base;
+        let u = turns(local.z, local.x);
+        let (normal, v) = match part {
+            0 => (
+                vec3(local.x, T::zero(), local.z) / self.radius,
+                local.y / self.height,
+            ),
+            1 => (
+                vec3(T::zero(), -T::one(), T::zero()),
+                (local.x * local.x + local.z * local.z).sqrt() / self.radius,
+            ),
+            _ => (
+                vec3(T::zero(), T::one(), T::zero()),
+                (local.x * local.x + local.z * local.z).sqrt() / self.radius,
+            ),
+        };
+        let around = vec3(-local.z, T::zero(), local.x);
+        let dpdv = if part == 0 {
+            vec3(T::zero(), self.height, T::zero())
+        } else {
+            vec3(local.x, T::zero(), local.z)
+        };
+        let mut rec = HitRecord::new(r, temp, p, normal, Rc::clone(&self.material));
+        rec.set_uv(Vector2::new(u, v));
+        rec.set_tangents(around, dpdv);
+        rec
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Cylinder<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        self.crossings(r)
+            .into_iter()
+            .find(|(temp, _)| *temp > t.start && *temp < t.end)
+            .map(|(temp, part)| self.hit_record(r, temp, part))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let r = self.radius.abs();
+        Some(Aabb::new(
+            self.base - vec3(r, T::zero(), r),
+            self.base + vec3(r, self.height, r),
+        ))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Solid<T> for Cylinder<T> {
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>> {
+        convex_span(
+            self.crossings(r)
+                .into_iter()
+                .map(|(temp, part)| self.hit_record(r, temp, part))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use cgmath::InnerSpace;
+    use std::f64::consts::PI;
+
+    fn cylinder() -> Cylinder<f64> {
+        Cylinder::new(
+            vec3(1.0, -1.0, 2.0),
+            0.5,
+            2.0,
+            Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn side_hits_sampled_points() {
+        let cylinder = cylinder();
+        for i in 0..16 {
+            let u = (f64::from(i) + 0.5) / 16.0;
+            let v = f64::from(i) / 15.0 * 0.9 + 0.05;
+            let phi = u * 2.0 * PI;
+            let normal = vec3(phi.cos(), 0.0, phi.sin());
+            let target = vec3(1.0, -1.0, 2.0) + normal * 0.5 + vec3(0.0, 2.0 * v, 0.0);
+            let r = Ray::new(target + normal * 3.0, -normal);
+            let rec = cylinder.hit(&r, 0.0..f64::MAX).unwrap();
+            assert!((rec.get_t() - 3.0).abs() < 1e-9);
+            assert!((rec.get_p() - target).magnitude() < 1e-9);
+            assert!((rec.get_normal() - normal).magnitude() < 1e-9);
+            assert!((rec.get_uv() - Vector2::new(u, v)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn caps_hit_sampled_points() {
+        let cylinder = cylinder();
+        for i in 0..16 {
+            let u = (f64::from(i) + 0.5) / 16.0;
+            let v = f64::from(i) / 16.0 * 0.9 + 0.05;
+            let phi = u * 2.0 * PI;
+            let offset = vec3(phi.cos(), 0.0, phi.sin()) * 0.5 * v;
+            let top = vec3(1.0, 1.0, 2.0) + offset;
+            let rec = cylinder
+                .hit(
+                    &Ray::new(top + vec3(0.0, 5.0, 0.0), vec3(0.0, -1.0, 0.0)),
+                    0.0..f64::MAX,
+                )
+                .unwrap();
+            assert!((rec.get_p() - top).magnitude() < 1e-9);
+            assert!((rec.get_normal() - vec3(0.0, 1.0, 0.0)).magnitude() < 1e-9);
+            assert!((rec.get_uv() - Vector2::new(u, v)).magnitude() < 1e-9);
+
+            let bottom = vec3(1.0, -1.0, 2.0) + offset;
+            let rec = cylinder
+                .hit(
+                    &Ray::new(bottom - vec3(0.0, 5.0, 0.0), vec3(0.0, 1.0, 0.0)),
+                    0.0..f64::MAX,
+                )
+                .unwrap();
+            assert!((rec.get_p() - bottom).magnitude() < 1e-9);
+            assert!((rec.get_normal() - vec3(0.0, -1.0, 0.0)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn misses_beyond_the_caps() {
+        let cylinder = cylinder();
+        let above = Ray::new(vec3(3.0, 1.5, 2.0), vec3(-1.0, 0.0, 0.0));
+        assert!(cylinder.hit(&above, 0.0..f64::MAX).is_none());
+        let beside = Ray::new(vec3(1.6, 5.0, 2.0), vec3(0.0, -1.0, 0.0));
+        assert!(cylinder.hit(&beside, 0.0..f64::MAX).is_none());
+    }
+
+    #[test]
+    fn rays_along_the_axis_only_cross_the_caps() {
+        let cylinder = cylinder();
+        // the side's quadratic vanishes entirely for a ray parallel to the axis
+        let down = Ray::new(vec3(1.0, 5.0, 2.0), vec3(0.0, -1.0, 0.0));
+        let rec = cylinder.hit(&down, 0.0..f64::MAX).unwrap();
+        assert!((rec.get_t() - 4.0).abs() < 1e-9);
+        assert!((rec.get_normal() - vec3(0.0, 1.0, 0.0)).magnitude() < 1e-9);
+        let spans = cylinder.spans(&down);
+        assert_eq!(spans.len(), 1);
+        assert!((spans[0].exit.get_t() - 6.0).abs() < 1e-9);
+        // a zero direction has no crossings at all
+        let stuck = Ray::new(vec3(1.0, 0.0, 2.0), vec3(0.0, 0.0, 0.0));
+        assert!(cylinder.hit(&stuck, 0.001..f64::MAX).is_none());
+        assert!(cylinder.spans(&stuck).is_empty());
+    }
+
+    #[test]
+    fn crossings_drop_nan_and_sort() {
+        let nan = std::f64::NAN;
+        let mut crossings = vec![(2.0, 0), (nan, 1), (-1.0, 2), (nan, 0), (0.5, 1)];
+        sort_crossings(&mut crossings, |c| c.0);
+        assert_eq!(crossings, vec![(-1.0, 2), (0.5, 1), (2.0, 0)]);
+    }
+}
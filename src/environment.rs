@@ -0,0 +1,352 @@
+use super::{AreaLight, ImageTexture, Texture, WrapMode};
+use cgmath::{vec3, InnerSpace, Vector3};
+use rand::distributions::Standard;
+use rand::prelude::*;
+use std::f64::consts::PI;
+use std::io::BufReader;
+use std::path::Path;
+
+// light arriving from infinitely far away, seen by rays that leave the scene
+pub trait Environment<T> {
+    fn radiance(&self, direction: &Vector3<T>) -> Vector3<T>;
+}
+
+pub struct ConstantEnvironment<T> {
+    color: Vector3<T>,
+}
+
+impl<T> ConstantEnvironment<T> {
+    pub fn new(color: Vector3<T>) -> Self {
+        Self { color }
+    }
+}
+
+impl<T: Copy> Environment<T> for ConstantEnvironment<T> {
+    fn radiance(&self, _direction: &Vector3<T>) -> Vector3<T> {
+        self.color
+    }
+}
+
+// blends from straight down to straight up
+pub struct GradientEnvironment<T> {
+    bottom: Vector3<T>,
+    top: Vector3<T>,
+}
+
+impl<T> GradientEnvironment<T> {
+    pub fn new(bottom: Vector3<T>, top: Vector3<T>) -> Self {
+        Self { bottom, top }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Environment<T> for GradientEnvironment<T> {
+    fn radiance(&self, direction: &Vector3<T>) -> Vector3<T> {
+        let half = T::from(0.5).unwrap();
+        let t = half * (direction.normalize().y + T::one());
+        self.bottom * (T::one() - t) + self.top * t
+    }
+}
+
+// a cumulative distribution over a run of bins
+struct Cdf<T> {
+    cdf: Vec<T>,
+}
+
+impl<T: cgmath::BaseFloat> Cdf<T> {
+    // all black bins are treated as equally likely rather than impossible
+    fn new(weights: &[T]) -> Self {
+        let uniform = weights.iter().all(|w| *w <= T::zero());
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(T::zero());
+        for w in weights {
+            let w = if uniform { T::one() } else { w.max(T::zero()) };
+            let last = cdf[cdf.len() - 1];
+            cdf.push(last + w);
+        }
+        Self { cdf }
+    }
+
+    fn total(&self) -> T {
+        self.cdf[self.cdf.len() - 1]
+    }
+
+    // the probability of picking bin `i`
+    fn probability(&self, i: usize) -> T {
+        (self.cdf[i + 1] - self.cdf[i]) / self.total()
+    }
+
+    // the bin that `xi` in [0, 1) lands in
+    fn sample(&self, xi: T) -> usize {
+        let target = xi * self.total();
+        let i = self.cdf.partition_point(|c| *c <= target);
+        i.max(1).min(self.cdf.len() - 1) - 1
+    }
+}
+
+// an equirectangular (latitude/longitude) image, +y at the top row and u running around the y
+// axis from -x the same way sphere uvs do. it can be sampled as a light in proportion to the
+// luminance of its texels
+pub struct HdrEnvironment<T> {
+    image: ImageTexture<T>,
+    width: usize,
+    height: usize,
+    rotation: T,
+    intensity: T,
+    rows: Cdf<T>,
+    columns: Vec<Cdf<T>>,
+}
+
+fn luminance<T: cgmath::BaseFloat>(c: &Vector3<T>) -> T {
+    c.dot(vec3(
+        T::from(0.2126).unwrap(),
+        T::from(0.7152).unwrap(),
+        T::from(0.0722).unwrap(),
+    ))
+}
+
+impl<T: cgmath::BaseFloat> HdrEnvironment<T> {
+    // texels are linear radiance, stored row by row from the top
+    pub fn new(width: usize, height: usize, texels: Vec<Vector3<T>>) -> Self {
+        let pi = T::from(PI).unwrap();
+        // rows near the poles cover less of the sphere
+        let (row_weights, columns): (Vec<_>, Vec<_>) = texels
+            .chunks(width)
+            .enumerate()
+            .map(|(y, row)| {
+                let theta =
+                    (T::from(y).unwrap() + T::from(0.5).unwrap()) * pi / T::from(height).unwrap();
+                let weights: Vec<_> = row.iter().map(|c| luminance(c) * theta.sin()).collect();
+                let total = weights
+                    .iter()
+                    .fold(T::zero(), |sum, w| sum + w.max(T::zero()));
+                (total, Cdf::new(&weights))
+            })
+            .unzip();
+        // an all black row's columns fall back to uniform, but the row itself is never picked
+        // unless the whole map is black
+        let rows = Cdf::new(&row_weights);
+        let mut image = ImageTexture::new(width, height, texels);
+        image.set_wrap(WrapMode::Repeat, WrapMode::Clamp);
+        Self {
+            image,
+            width,
+            height,
+            rotation: T::zero(),
+            intensity: T::one(),
+            rows,
+            columns,
+        }
+    }
+
+    // radiance (.hdr) files, which already hold linear values
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, image::ImageError> {
+        let file = std::fs::File::open(path)?;
+        let decoder = image::hdr::HDRDecoder::new(BufReader::new(file))?;
+        let metadata = decoder.metadata();
+        // the decoder accepts a header with no pixels, which leaves nothing to sample
+        if metadata.width == 0 || metadata.height == 0 {
+            return Err(image::ImageError::DimensionError);
+        }
+        let texels = decoder
+            .read_image_hdr()?
+            .iter()
+            .map(|p| {
+                vec3(
+                    T::from(p[0]).unwrap(),
+                    T::from(p[1]).unwrap(),
+                    T::from(p[2]).unwrap(),
+                )
+            })
+            .collect();
+        Ok(Self::new(
+            metadata.width as usize,
+            metadata.height as usize,
+            texels,
+        ))
+    }
+
+    // turns the map about the y axis, in radians
+    pub fn set_rotation(&mut self, rotation: T) {
+        self.rotation = rotation;
+    }
+
+    // scales the radiance of every texel
+    pub fn set_intensity(&mut self, intensity: T) {
+        self.intensity = intensity;
+    }
+
+    // (u, v) with v running from 0 at the top row to 1 at the bottom one
+    fn to_uv(&self, direction: &Vector3<T>) -> (T, T) {
+        let pi = T::from(PI).unwrap();
+        let two_pi = pi + pi;
+        let d = direction.normalize();
+        let phi = (-d.z).atan2(d.x) + pi - self.rotation;
+        let u = phi / two_pi;
+        let theta = d.y.max(-T::one()).min(T::one()).acos();
+        (u - u.floor(), theta / pi)
+    }
+
+    fn to_direction(&self, u: T, v: T) -> Vector3<T> {
+        let pi = T::from(PI).unwrap();
+        let phi = u * (pi + pi) - pi + self.rotation;
+        let theta = v * pi;
+        vec3(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            -theta.sin() * phi.sin(),
+        )
+    }
+
+    fn texel(&self, u: T, v: T) -> (usize, usize) {
+        let x = (u * T::from(self.width).unwrap()).to_usize().unwrap_or(0);
+        let y = (v * T::from(self.height).unwrap()).to_usize().unwrap_or(0);
+        (x.min(self.width - 1), y.min(self.height - 1))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Environment<T> for HdrEnvironment<T> {
+    fn radiance(&self, direction: &Vector3<T>) -> Vector3<T> {
+        let (u, v) = self.to_uv(direction);
+        let p = vec3(T::zero(), T::zero(), T::zero());
+        self.image.value(u, T::one() - v, &p) * self.intensity
+    }
+}
+
+// where the ray starts doesn't matter for light from infinitely far away
+impl<T> AreaLight<T> for HdrEnvironment<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    fn sample(&self, _origin: &Vector3<T>) -> Vector3<T> {
+        let mut rng = thread_rng();
+        let y = self.rows.sample(rng.gen());
+        let x = self.columns[y].sample(rng.gen());
+        let u = (T::from(x).unwrap() + rng.gen::<T>()) / T::from(self.width).unwrap();
+        let v = (T::from(y).unwrap() + rng.gen::<T>()) / T::from(self.height).unwrap();
+        self.to_direction(u, v)
+    }
+
+    fn pdf(&self, _origin: &Vector3<T>, direction: &Vector3<T>) -> T {
+        let pi = T::from(PI).unwrap();
+        let (u, v) = self.to_uv(direction);
+        let sin_theta = (v * pi).sin();
+        if sin_theta <= T::zero() {
+            return T::zero();
+        }
+        let (x, y) = self.texel(u, v);
+        let probability = self.rows.probability(y) * self.columns[y].probability(x);
+        // from a texel's share of the image to density over the sphere
+        let texels = T::from(self.width * self.height).unwrap();
+        probability * texels / (T::from(2.0).unwrap() * pi * pi * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> HdrEnvironment<f64> {
+        let (width, height) = (16, 8);
+        let texels = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                if x == 3 && y == 2 {
+                    vec3(50.0, 40.0, 30.0)
+                } else {
+                    vec3(0.1, 0.2, 0.3) * (1.0 + (x + y) as f64 * 0.1)
+                }
+            })
+            .collect();
+        HdrEnvironment::new(width, height, texels)
+    }
+
+    #[test]
+    fn directions_round_trip_through_uv() {
+        let mut env = map();
+        env.set_rotation(0.7);
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.93, 0.81)] {
+            let (u2, v2) = env.to_uv(&env.to_direction(u, v));
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let mut env = map();
+        env.set_rotation(2.0);
+        let origin = vec3(0.0, 0.0, 0.0);
+        // a midpoint rule over (phi, cos theta), which is uniform over the sphere
+        let n = 400;
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let phi = (i as f64 + 0.5) / n as f64 * 2.0 * PI;
+                let y = (j as f64 + 0.5) / n as f64 * 2.0 - 1.0;
+                let s = (1.0 - y * y).sqrt();
+                let d = vec3(s * phi.cos(), y, s * phi.sin());
+                sum += env.pdf(&origin, &d);
+            }
+        }
+        let integral = sum * 4.0 * PI / (n * n) as f64;
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+    }
+
+    #[test]
+    fn samples_favour_the_bright_texel() {
+        let env = map();
+        let origin = vec3(0.0, 0.0, 0.0);
+        // it holds a little over half of the map's weight
+        let bright = (0..1000)
+            .filter(|_| {
+                let (u, v) = env.to_uv(&env.sample(&origin));
+                env.texel(u, v) == (3, 2)
+            })
+            .count();
+        assert!(bright > 400, "{}", bright);
+    }
+
+    #[test]
+    fn black_rows_are_never_sampled() {
+        // the top half of the map is black
+        let (width, height) = (8, 8);
+        let texels = (0..width * height)
+            .map(|i| {
+                if i / width < height / 2 {
+                    vec3(0.0, 0.0, 0.0)
+                } else {
+                    vec3(1.0, 1.0, 1.0)
+                }
+            })
+            .collect();
+        let env = HdrEnvironment::new(width, height, texels);
+        let origin = vec3(0.0, 0.0, 0.0);
+        for y in 0..height / 2 {
+            assert_eq!(env.rows.probability(y), 0.0);
+        }
+        for _ in 0..1000 {
+            let d = env.sample(&origin);
+            assert!(d.y <= 0.0, "{:?}", d);
+            assert!(env.pdf(&origin, &d) > 0.0);
+        }
+        assert_eq!(env.pdf(&origin, &vec3(0.3, 0.8, 0.1)), 0.0);
+
+        // an entirely black map is still sampled, uniformly by texel
+        let black = HdrEnvironment::new(4, 2, vec![vec3(0.0, 0.0, 0.0); 8]);
+        assert_eq!(black.rows.probability(0), 0.5);
+        assert_eq!(black.columns[1].probability(3), 0.25);
+    }
+
+    #[test]
+    fn empty_maps_fail_to_load() {
+        let path = std::env::temp_dir().join(format!("empty-{}.hdr", std::process::id()));
+        std::fs::write(&path, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 4\n").unwrap();
+        let map = HdrEnvironment::<f64>::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        match map {
+            Err(image::ImageError::DimensionError) => {}
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("loaded an empty map"),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use super::{Aabb, HitRecord, HitTable, Isotropic, Material, Ray};
+use cgmath::{vec3, InnerSpace, Vector3};
+use rand::distributions::Standard;
+use rand::prelude::*;
+use std::ops::Range;
+use std::rc::Rc;
+
+// densities sampled at cell centers, stored with x varying fastest
+pub struct DensityGrid<T> {
+    resolution: [usize; 3],
+    data: Vec<T>,
+    max_density: T,
+}
+
+impl<T: cgmath::BaseFloat> DensityGrid<T> {
+    pub fn new(resolution: [usize; 3], data: Vec<T>) -> Self {
+        assert_eq!(
+            resolution[0] * resolution[1] * resolution[2],
+            data.len(),
+            "density grid resolution doesn't match its data"
+        );
+        assert!(data.iter().all(|d| *d >= T::zero()), "negative density");
+        let max_density = data.iter().fold(T::zero(), |acc, d| acc.max(*d));
+        Self {
+            resolution,
+            data,
+            max_density,
+        }
+    }
+
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    pub fn max_density(&self) -> T {
+        self.max_density
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> T {
+        self.data[x + self.resolution[0] * (y + self.resolution[1] * z)]
+    }
+
+    // trilinear interpolation, `p` is in [0, 1] over the whole grid
+    pub fn lookup(&self, p: Vector3<T>) -> T {
+        let half = T::from(0.5).unwrap();
+        let mut cell = [0usize; 3];
+        let mut next = [0usize; 3];
+        let mut frac = [T::zero(); 3];
+        for axis in 0..3 {
+            let last = self.resolution[axis] - 1;
+            let g = (p[axis] * T::from(self.resolution[axis]).unwrap() - half)
+                .max(T::zero())
+                .min(T::from(last).unwrap());
+            cell[axis] = g.floor().to_usize().unwrap();
+            next[axis] = (cell[axis] + 1).min(last);
+            frac[axis] = g - g.floor();
+        }
+        let lerp = |a: T, b: T, t: T| a + (b - a) * t;
+        let along_x = |y: usize, z: usize| {
+            lerp(
+                self.voxel(cell[0], y, z),
+                self.voxel(next[0], y, z),
+                frac[0],
+            )
+        };
+        let along_y = |z: usize| lerp(along_x(cell[1], z), along_x(next[1], z), frac[1]);
+        lerp(along_y(cell[2]), along_y(next[2]), frac[2])
+    }
+}
+
+// a heterogeneous medium, sampled without bias by tracking against the grid's maximum density
+pub struct GridVolume<T> {
+    grid: DensityGrid<T>,
+    bounds: Aabb<T>,
+    density_scale: T,
+    phase_function: Rc<dyn Material<T>>,
+}
+
+impl<T> GridVolume<T>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+{
+    pub fn new(
+        grid: DensityGrid<T>,
+        bounds: Aabb<T>,
+        density_scale: T,
+        albedo: Vector3<T>,
+    ) -> Self {
+        Self {
+            grid,
+            bounds,
+            density_scale,
+            phase_function: Rc::new(Isotropic::new(albedo)),
+        }
+    }
+
+    pub fn density(&self, p: Vector3<T>) -> T {
+        let extent = self.bounds.extent();
+        let local = p - self.bounds.min();
+        self.grid.lookup(vec3(
+            local.x / extent.x,
+            local.y / extent.y,
+            local.z / extent.z,
+        )) * self.density_scale
+    }
+
+    fn majorant(&self) -> T {
+        self.grid.max_density() * self.density_scale
+    }
+
+    // steps through the bounds with exponentially distributed distances against the majorant,
+    // calling `f` with each tentative collision until it returns false
+    fn track<F: FnMut(T) -> bool>(&self, r: &Ray<T>, t: Range<T>, mut f: F) {
+        let majorant = self.majorant();
+        let span = match self.bounds.intersect(r, t) {
+            Some(span) => span,
+            None => return,
+        };
+        if majorant <= T::zero() {
+            return;
+        }
+        let ray_length = r.direction().magnitude();
+        let mut rng = thread_rng();
+        let mut temp = span.start;
+        loop {
+            temp -= (T::one() - rng.gen::<T>()).ln() / (majorant * ray_length);
+            if temp >= span.end || !f(temp) {
+                return;
+            }
+        }
+    }
+
+    // ratio tracking estimate of the fraction of light passing through unscattered, for
+    // shadow rays that cross the volume
+    pub fn transmittance(&self, r: &Ray<T>, t: Range<T>) -> T {
+        let majorant = self.majorant();
+        let mut transmittance = T::one();
+        self.track(r, t, |temp| {
+            transmittance *= T::one() - self.density(r.point_at_parameter(temp)) / majorant;
+            true
+        });
+        transmittance
+    }
+}
+
+impl<T> HitTable<T> for GridVolume<T>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+{
+    // delta tracking, a tentative collision is real with probability density / majorant
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let majorant = self.majorant();
+        let mut rng = thread_rng();
+        let mut hit = None;
+        self.track(r, t, |temp| {
+            let p = r.point_at_parameter(temp);
+            if rng.gen::<T>() * majorant < self.density(p) {
+                hit = Some(temp);
+                false
+            } else {
+                true
+            }
+        });
+        // scattering is isotropic, so the normal is arbitrary
+        hit.map(|temp| {
+            HitRecord::new(
+                r,
+                temp,
+                r.point_at_parameter(temp),
+                vec3(T::one(), T::zero(), T::zero()),
+                Rc::clone(&self.phase_function),
+            )
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_blend_between_voxel_centers() {
+        // corner (x, y, z) of the 2x2x2 grid holds 1 + x + 2y + 4z
+        let grid = DensityGrid::new([2, 2, 2], (1..=8).map(f64::from).collect());
+        let at = |x, y, z| grid.lookup(vec3(x, y, z));
+        assert_eq!(at(0.25, 0.25, 0.25), 1.0);
+        assert_eq!(at(0.75, 0.75, 0.75), 8.0);
+        assert_eq!(at(0.75, 0.25, 0.75), 6.0);
+        assert_eq!(at(0.5, 0.25, 0.25), 1.5);
+        assert_eq!(at(0.25, 0.5, 0.25), 2.0);
+        assert_eq!(at(0.25, 0.25, 0.5), 3.0);
+        assert_eq!(at(0.5, 0.5, 0.5), 4.5);
+        // a quarter of the way from one center to the next in x, halfway in y and z
+        assert!((at(0.375, 0.5, 0.5) - 4.25).abs() < 1e-12);
+        // past the outermost centers the nearest voxel holds
+        assert_eq!(at(0.0, 0.0, 0.0), 1.0);
+        assert_eq!(at(1.0, 0.1, 1.0), 6.0);
+        assert_eq!(grid.max_density(), 8.0);
+    }
+
+    // hit frequency and mean transmittance along +x through the volume
+    fn estimates(volume: &GridVolume<f64>) -> (f64, f64) {
+        let r = Ray::new(vec3(-1.0, 0.5, 0.5), vec3(2.0, 0.0, 0.0));
+        let n = 20_000;
+        let (mut hits, mut transmittance) = (0, 0.0);
+        for _ in 0..n {
+            if volume.hit(&r, 0.001..f64::MAX).is_some() {
+                hits += 1;
+            }
+            transmittance += volume.transmittance(&r, 0.001..f64::MAX);
+        }
+        (f64::from(hits) / f64::from(n), transmittance / f64::from(n))
+    }
+
+    #[test]
+    fn tracking_converges_to_beers_law() {
+        let bounds = Aabb::new(vec3(0.0, 0.0, 0.0), vec3(1.5, 1.0, 1.0));
+        let uniform = DensityGrid::new([4, 3, 2], vec![1.0; 24]);
+        let volume = GridVolume::new(uniform, bounds, 0.8, vec3(1.0, 1.0, 1.0));
+        let expected = (-0.8f64 * 1.5).exp();
+        let (hits, transmittance) = estimates(&volume);
+        assert!((hits - (1.0 - expected)).abs() < 0.015, "{}", hits);
+        assert!(
+            (transmittance - expected).abs() < 0.015,
+            "{}",
+            transmittance
+        );
+
+        // densities ramping from 0.5 to 1 across the middle half, an optical depth of 0.75
+        // per unit of grid, where ratio tracking has fractional weights to multiply
+        let bounds = Aabb::new(vec3(0.0, 0.0, 0.0), vec3(2.0, 1.0, 1.0));
+        let ramp = DensityGrid::new([2, 1, 1], vec![0.5, 1.0]);
+        let volume = GridVolume::new(ramp, bounds, 0.6, vec3(1.0, 1.0, 1.0));
+        let expected = (-0.6f64 * 0.75 * 2.0).exp();
+        let (hits, transmittance) = estimates(&volume);
+        assert!((hits - (1.0 - expected)).abs() < 0.015, "{}", hits);
+        assert!(
+            (transmittance - expected).abs() < 0.015,
+            "{}",
+            transmittance
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use super::triangle;
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::{vec3, InnerSpace, Vector2, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+// heights in [0, 1] sampled on a regular grid, stored with x varying fastest
+pub struct HeightMap<T> {
+    resolution: [usize; 2],
+    data: Vec<T>,
+}
+
+impl<T: cgmath::BaseFloat> HeightMap<T> {
+    pub fn new(resolution: [usize; 2], data: Vec<T>) -> Self {
+        assert_eq!(
+            resolution[0] * resolution[1],
+            data.len(),
+            "height map resolution doesn't match its data"
+        );
+        assert!(
+            resolution[0] >= 2 && resolution[1] >= 2,
+            "height maps need at least 2x2 samples"
+        );
+        Self { resolution, data }
+    }
+
+    pub fn resolution(&self) -> [usize; 2] {
+        self.resolution
+    }
+
+    pub fn get(&self, x: usize, z: usize) -> T {
+        self.data[x + self.resolution[0] * z]
+    }
+}
+
+// terrain spanning the x and z extent of `bounds`, with heights scaled from the bottom to the
+// top of them. rays walk the grid cells they cross and only test those cells' two triangles
+pub struct Heightfield<T> {
+    resolution: [usize; 2],
+    bounds: Aabb<T>,
+    vertices: Vec<Vector3<T>>,
+    normals: Vec<Vector3<T>>,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T: cgmath::BaseFloat> Heightfield<T> {
+    pub fn new(map: HeightMap<T>, bounds: Aabb<T>, material: Rc<dyn Material<T>>) -> Self {
+        let [nx, nz] = map.resolution();
+        let (min, extent) = (*bounds.min(), bounds.extent());
+        let vertices: Vec<_> = (0..nz)
+            .flat_map(|z| (0..nx).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                vec3(
+                    min.x + extent.x * T::from(x).unwrap() / T::from(nx - 1).unwrap(),
+                    min.y + extent.y * map.get(x, z),
+                    min.z + extent.z * T::from(z).unwrap() / T::from(nz - 1).unwrap(),
+                )
+            })
+            .collect();
+        // central differences, one sided along the edges
+        let at = |x: usize, z: usize| vertices[x + nx * z];
+        let normals = (0..nz)
+            .flat_map(|z| (0..nx).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                let dx = at((x + 1).min(nx - 1), z) - at(x.saturating_sub(1), z);
+                let dz = at(x, (z + 1).min(nz - 1)) - at(x, z.saturating_sub(1));
+                dz.cross(dx).normalize()
+            })
+            .collect();
+        Self {
+            resolution: map.resolution(),
+            bounds,
+            vertices,
+            normals,
+            material,
+        }
+    }
+
+    fn vertex(&self, x: usize, z: usize) -> usize {
+        x + self.resolution[0] * z
+    }
+
+    // the nearer hit on the two triangles of cell (x, z)
+    fn hit_cell(&self, r: &Ray<T>, t: &Range<T>, x: usize, z: usize) -> Option<HitRecord<T>> {
+        let corners = [
+            self.vertex(x, z),
+            self.vertex(x + 1, z),
+            self.vertex(x + 1, z + 1),
+            self.vertex(x, z + 1),
+        ];
+        let mut best: Option<([usize; 3], (T, T, T))> = None;
+        for indices in &[
+            [corners[0], corners[1], corners[2]],
+            [corners[0], corners[2], corners[3]],
+        ] {
+            let vertices = [
+                &self.vertices[indices[0]],
+                &self.vertices[indices[1]],
+                &self.vertices[indices[2]],
+            ];
+            let range = t.start..best.map_or(t.end, |(_, hit)| hit.0);
+            if let Some(hit) = triangle::intersect(vertices, r, &range) {
+                best = Some((*indices, hit));
+            }
+        }
+        let (indices, hit) = best?;
+        let uvs = [
+            self.uv(indices[0]),
+            self.uv(indices[1]),
+            self.uv(indices[2]),
+        ];
+        Some(triangle::hit_record(
+            [
+                &self.vertices[indices[0]],
+                &self.vertices[indices[1]],
+                &self.vertices[indices[2]],
+            ],
+            Some([
+                &self.normals[indices[0]],
+                &self.normals[indices[1]],
+                &self.normals[indices[2]],
+            ]),
+            Some([&uvs[0], &uvs[1], &uvs[2]]),
+            r,
+            hit,
+            &self.material,
+        ))
+    }
+
+    // uv runs from 0 to 1 across the whole map
+    fn uv(&self, vertex: usize) -> Vector2<T> {
+        let [nx, nz] = self.resolution;
+        Vector2::new(
+            T::from(vertex % nx).unwrap() / T::from(nx - 1).unwrap(),
+            T::from(vertex / nx).unwrap() / T::from(nz - 1).unwrap(),
+        )
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Heightfield<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let span = self.bounds.intersect(r, t.clone())?;
+        let (o, d) = (r.origin(), r.direction());
+        let min = self.bounds.min();
+        let extent = self.bounds.extent();
+        let cells = [self.resolution[0] - 1, self.resolution[1] - 1];
+        let cell_size = [
+            extent.x / T::from(cells[0]).unwrap(),
+            extent.z / T::from(cells[1]).unwrap(),
+        ];
+
+        // 2d dda over the xz grid, starting in the cell where the ray enters the bounds
+        let entry = r.point_at_parameter(span.start);
+        let mut cell = [0usize; 2];
+        let mut step = [0isize; 2];
+        let mut t_next = [T::infinity(); 2];
+        let mut t_delta = [T::infinity(); 2];
+        for (i, &axis) in [0, 2].iter().enumerate() {
+            let g = ((entry[axis] - min[axis]) / cell_size[i])
+                .floor()
+                .max(T::zero())
+                .min(T::from(cells[i] - 1).unwrap());
+            cell[i] = g.to_usize().unwrap();
+            let boundary = |c: usize| min[axis] + T::from(c).unwrap() * cell_size[i];
+            if d[axis] > T::zero() {
+                step[i] = 1;
+                t_next[i] = (boundary(cell[i] + 1) - o[axis]) / d[axis];
+                t_delta[i] = cell_size[i] / d[axis];
+            } else if d[axis] < T::zero() {
+                step[i] = -1;
+                t_next[i] = (boundary(cell[i]) - o[axis]) / d[axis];
+                t_delta[i] = -cell_size[i] / d[axis];
+            }
+        }
+
+        let mut t_enter = span.start;
+        loop {
+            let t_exit = t_next[0].min(t_next[1]).min(span.end);
+            // skip cells the ray passes entirely above or below
+            let [x, z] = cell;
+            let heights = [
+                self.vertices[self.vertex(x, z)].y,
+                self.vertices[self.vertex(x + 1, z)].y,
+                self.vertices[self.vertex(x, z + 1)].y,
+                self.vertices[self.vertex(x + 1, z + 1)].y,
+            ];
+            let low = heights.iter().fold(T::infinity(), |acc, h| acc.min(*h));
+            let high = heights.iter().fold(T::neg_infinity(), |acc, h| acc.max(*h));
+            let (y0, y1) = (o.y + t_enter * d.y, o.y + t_exit * d.y);
+            if y0.min(y1) <= high && y0.max(y1) >= low {
+                if let Some(rec) = self.hit_cell(r, &t, x, z) {
+                    return Some(rec);
+                }
+            }
+            if t_exit >= span.end {
+                return None;
+            }
+
+            let i = if t_next[0] < t_next[1] { 0 } else { 1 };
+            let next = cell[i] as isize + step[i];
+            if next < 0 || next >= cells[i] as isize {
+                return None;
+            }
+            cell[i] = next as usize;
+            t_enter = t_next[i];
+            t_next[i] += t_delta[i];
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use rand::prelude::*;
+
+    // every cell's triangles, for checking the grid walk against
+    fn brute_force(field: &Heightfield<f64>, r: &Ray<f64>, t: Range<f64>) -> Option<f64> {
+        let [nx, nz] = field.resolution;
+        let mut closest = t.end;
+        for z in 0..nz - 1 {
+            for x in 0..nx - 1 {
+                if let Some(rec) = field.hit_cell(r, &(t.start..closest), x, z) {
+                    closest = rec.get_t();
+                }
+            }
+        }
+        if closest < t.end {
+            Some(closest)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn grid_walk_matches_testing_every_cell() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let (nx, nz) = (9, 7);
+        let data = (0..nx * nz).map(|_| rng.gen_range(0.0, 1.0)).collect();
+        let field = Heightfield::new(
+            HeightMap::new([nx, nz], data),
+            Aabb::new(vec3(-2.0, 0.0, -1.5), vec3(2.0, 1.0, 1.5)),
+            Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))),
+        );
+        let mut hits = 0;
+        for i in 0..2000 {
+            let origin = vec3(
+                rng.gen_range(-4.0, 4.0),
+                rng.gen_range(-1.0, 3.0),
+                rng.gen_range(-3.0, 3.0),
+            );
+            // every tenth ray looks straight down, along a cell boundary for some of them
+            let direction = if i % 10 == 0 {
+                vec3(0.0, -1.0, 0.0)
+            } else {
+                let target = vec3(
+                    rng.gen_range(-2.0, 2.0),
+                    rng.gen_range(0.0, 1.0),
+                    rng.gen_range(-1.5, 1.5),
+                );
+                target - origin
+            };
+            let r = Ray::new(origin, direction);
+            let expected = brute_force(&field, &r, 0.001..f64::MAX);
+            let found = field.hit(&r, 0.001..f64::MAX).map(|rec| rec.get_t());
+            match (expected, found) {
+                (Some(a), Some(b)) => {
+                    assert!((a - b).abs() < 1e-9, "ray {}: {} vs {}", i, a, b);
+                    hits += 1;
+                }
+                (None, None) => (),
+                _ => panic!("ray {}: expected {:?}, found {:?}", i, expected, found),
+            }
+        }
+        assert!(hits > 1000);
+    }
+}
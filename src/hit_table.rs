@@ -1,28 +1,50 @@
 extern crate cgmath;
 
-use crate::Material;
-use cgmath::Vector3;
+use crate::{Aabb, Material, Onb, Ray};
+use cgmath::{InnerSpace, Vector2, Vector3};
 use std::rc::Rc;
 
+#[derive(Clone)]
 pub struct HitRecord<T> {
     t: T,
     p: Vector3<T>,
+    // always faces against the incoming ray, `front_face` says which side that is
     normal: Vector3<T>,
+    front_face: bool,
     material: Rc<dyn super::Material<T>>,
+    // (u, v) weights of the second and third vertex, only set by triangles
+    barycentric: Option<Vector2<T>>,
+    uv: Vector2<T>,
+    // unit vectors in the surface along increasing u and v, perpendicular to the normal
+    tangent: Vector3<T>,
+    bitangent: Vector3<T>,
 }
 
-impl<T: std::marker::Copy> HitRecord<T> {
+impl<T: cgmath::BaseFloat> HitRecord<T> {
     pub fn new(
+        r: &Ray<T>,
         t: T,
         p: Vector3<T>,
-        normal: Vector3<T>,
+        outward_normal: Vector3<T>,
         material: Rc<dyn super::Material<T>>,
     ) -> Self {
+        let front_face = r.direction().dot(outward_normal) <= T::zero();
+        let normal = if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+        let frame = Onb::from_w(normal);
         Self {
             t,
             p,
             normal,
+            front_face,
             material,
+            barycentric: None,
+            uv: Vector2::new(T::zero(), T::zero()),
+            tangent: *frame.u(),
+            bitangent: *frame.v(),
         }
     }
 
@@ -50,13 +72,70 @@ impl<T: std::marker::Copy> HitRecord<T> {
         self.normal = normal;
     }
 
+    pub fn get_front_face(&self) -> bool {
+        self.front_face
+    }
+
+    pub fn set_front_face(&mut self, front_face: bool) {
+        self.front_face = front_face;
+    }
+
     pub fn get_material(&self) -> &Rc<dyn Material<T>> {
         &self.material
     }
+
+    pub fn get_barycentric(&self) -> Option<Vector2<T>> {
+        self.barycentric
+    }
+
+    pub fn set_barycentric(&mut self, barycentric: Option<Vector2<T>>) {
+        self.barycentric = barycentric;
+    }
+
+    pub fn get_uv(&self) -> &Vector2<T> {
+        &self.uv
+    }
+
+    pub fn set_uv(&mut self, uv: Vector2<T>) {
+        self.uv = uv;
+    }
+
+    pub fn get_tangent(&self) -> &Vector3<T> {
+        &self.tangent
+    }
+
+    pub fn get_bitangent(&self) -> &Vector3<T> {
+        &self.bitangent
+    }
+
+    // builds the frame from the surface derivatives dp/du and dp/dv, which needn't be unit
+    // length or perpendicular. degenerate derivatives keep the current frame
+    pub fn set_tangents(&mut self, dpdu: Vector3<T>, dpdv: Vector3<T>) {
+        let tangent = dpdu - self.normal * self.normal.dot(dpdu);
+        if tangent.magnitude2() <= T::epsilon() * dpdu.magnitude2() {
+            return;
+        }
+        let tangent = tangent.normalize();
+        let bitangent = self.normal.cross(tangent);
+        self.tangent = tangent;
+        self.bitangent = if bitangent.dot(dpdv) < T::zero() {
+            -bitangent
+        } else {
+            bitangent
+        };
+    }
 }
 
 pub trait HitTable<T> {
+    // geometry with a surface parameterization reports it through `HitRecord::set_uv` and
+    // `HitRecord::set_tangents`, anything else is left at uv (0, 0) with an arbitrary frame
+    // around the normal
     fn hit(&self, r: &super::ray::Ray<T>, t: std::ops::Range<T>) -> Option<HitRecord<T>>;
+
+    // unbounded geometry returns None and can't be placed inside a Bvh node
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        None
+    }
 }
 
 pub struct HitTableList<T> {
@@ -71,9 +150,13 @@ impl<T> HitTableList<T> {
     pub fn add(&mut self, ht: Box<dyn HitTable<T>>) {
         self.list.push(ht)
     }
+
+    pub fn into_vec(self) -> std::vec::Vec<Box<dyn HitTable<T>>> {
+        self.list
+    }
 }
 
-impl<T: cgmath::BaseNum> HitTable<T> for HitTableList<T> {
+impl<T: cgmath::BaseFloat> HitTable<T> for HitTableList<T> {
     fn hit(&self, r: &super::ray::Ray<T>, t: std::ops::Range<T>) -> Option<HitRecord<T>> {
         let mut closest_so_far = t.end;
         let mut hit = None;
@@ -85,4 +168,165 @@ impl<T: cgmath::BaseNum> HitTable<T> for HitTableList<T> {
         }
         hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let mut iter = self.list.iter();
+        let first = iter.next()?.bounding_box()?;
+        iter.try_fold(first, |acc, ht| Some(acc.surrounding(&ht.bounding_box()?)))
+    }
+}
+
+// culls back faces, so the object can only be hit from the side its normals point to
+pub struct OneSided<T> {
+    object: Box<dyn HitTable<T>>,
+}
+
+impl<T> OneSided<T> {
+    pub fn new(object: Box<dyn HitTable<T>>) -> Self {
+        Self { object }
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for OneSided<T> {
+    fn hit(&self, r: &Ray<T>, t: std::ops::Range<T>) -> Option<HitRecord<T>> {
+        // a culled back face can hide a front face further along
+        let mut start = t.start;
+        loop {
+            let rec = self.object.hit(r, start..t.end)?;
+            if rec.get_front_face() {
+                return Some(rec);
+            }
+            start = rec.get_t();
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.object.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Dielectric, FlipFace, Lambertian, Sphere, XyRect};
+    use cgmath::vec3;
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn normals_face_against_the_ray() {
+        let sphere = Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material());
+        let entering = Ray::new(vec3(0.0, 0.0, -3.0), vec3(0.0, 0.0, 1.0));
+        let rec = sphere.hit(&entering, 0.0..f64::MAX).unwrap();
+        assert_eq!(rec.get_t(), 2.0);
+        assert!(rec.get_front_face());
+        assert_eq!(*rec.get_normal(), vec3(0.0, 0.0, -1.0));
+
+        // leaving through the far side, where the outward normal points along the ray
+        let leaving = Ray::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0));
+        let rec = sphere.hit(&leaving, 0.0..f64::MAX).unwrap();
+        assert_eq!(rec.get_t(), 1.0);
+        assert!(!rec.get_front_face());
+        assert_eq!(*rec.get_normal(), vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn tangents_are_made_orthonormal() {
+        let r = Ray::new(vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new(
+            &r,
+            1.0,
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 0.0, 1.0),
+            material(),
+        );
+        // skewed, off the surface and of any length, with dp/dv on the -y side
+        rec.set_tangents(vec3(3.0, 1.0, 2.0), vec3(0.5, -2.0, -1.0));
+        let (t, b) = (*rec.get_tangent(), *rec.get_bitangent());
+        assert!((t - vec3(3.0, 1.0, 0.0).normalize()).magnitude() < 1e-9);
+        assert!((b - vec3(1.0, -3.0, 0.0).normalize()).magnitude() < 1e-9);
+        assert!(b.dot(vec3(0.5, -2.0, -1.0)) > 0.0);
+    }
+
+    #[test]
+    fn degenerate_tangents_keep_the_default_frame() {
+        let r = Ray::new(vec3(1.0, 1.0, 1.0), vec3(-1.0, -1.0, -1.0));
+        let normal = vec3(1.0, 1.0, 1.0).normalize();
+        let frame = Onb::from_w(normal);
+        for &dpdu in &[vec3(0.0, 0.0, 0.0), normal * 2.0] {
+            let mut rec = HitRecord::new(&r, 1.0, vec3(0.0, 0.0, 0.0), normal, material());
+            rec.set_tangents(dpdu, vec3(0.0, 1.0, 0.0));
+            assert_eq!(rec.get_tangent(), frame.u());
+            assert_eq!(rec.get_bitangent(), frame.v());
+        }
+    }
+
+    #[test]
+    fn one_sided_culls_back_faces() {
+        let sphere = OneSided::new(Box::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material())));
+        let outside = Ray::new(vec3(0.0, 0.0, -3.0), vec3(0.0, 0.0, 1.0));
+        assert_eq!(sphere.hit(&outside, 0.0..f64::MAX).unwrap().get_t(), 2.0);
+        let inside = Ray::new(vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0));
+        assert!(sphere.hit(&inside, 0.0..f64::MAX).is_none());
+
+        // a rect seen from behind in front of one seen from the front
+        let mut list = HitTableList::new();
+        list.add(Box::new(XyRect::new(
+            (0.0, 1.0),
+            (0.0, 1.0),
+            1.0,
+            material(),
+        )));
+        list.add(Box::new(FlipFace::new(Box::new(XyRect::new(
+            (0.0, 1.0),
+            (0.0, 1.0),
+            2.0,
+            material(),
+        )))));
+        let r = Ray::new(vec3(0.5, 0.5, 0.0), vec3(0.0, 0.0, 1.0));
+        assert_eq!(list.hit(&r, 0.0..f64::MAX).unwrap().get_t(), 1.0);
+        let rec = OneSided::new(Box::new(list))
+            .hit(&r, 0.0..f64::MAX)
+            .unwrap();
+        assert_eq!(rec.get_t(), 2.0);
+        assert!(rec.get_front_face());
+        assert!(OneSided::new(Box::new(XyRect::new(
+            (0.0, 1.0),
+            (0.0, 1.0),
+            1.0,
+            material()
+        )))
+        .hit(&r, 0.0..f64::MAX)
+        .is_none());
+    }
+
+    #[test]
+    fn dielectrics_pick_their_side_from_front_face() {
+        let glass = Dielectric::new(1.5);
+        // 60 degrees off the normal, past the critical angle from inside the glass
+        let (s, c) = (60f64.to_radians().sin(), 60f64.to_radians().cos());
+        let r = Ray::new(vec3(-s, -c, 0.0), vec3(s, c, 0.0));
+        let mut rec = HitRecord::new(
+            &r,
+            1.0,
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            material(),
+        );
+        assert!(!rec.get_front_face());
+        // totally reflected back inside, every time
+        for _ in 0..200 {
+            let scattered = glass.scatter(&r, &rec).unwrap();
+            assert!(scattered.ray.direction().y < 0.0);
+        }
+
+        // the same geometry taken as entering the glass mostly refracts through
+        rec.set_front_face(true);
+        let through = (0..200)
+            .filter(|_| glass.scatter(&r, &rec).unwrap().ray.direction().y > 0.0)
+            .count();
+        assert!(through > 150);
+    }
 }
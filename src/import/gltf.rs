@@ -0,0 +1,396 @@
+use crate::{
+    Bvh, Camera, Dielectric, DiffuseLight, HitTable, HitTableList, Instance, Lambertian, Material,
+    MeshData, MeshError, Metal, TriangleMesh,
+};
+use ::gltf::buffer::Source;
+use ::gltf::material::AlphaMode;
+use ::gltf::mesh::Mode;
+use ::gltf::{Gltf, Node};
+use cgmath::{vec2, vec3, InnerSpace, Matrix4, SquareMatrix, Vector3};
+use rand::distributions::{Distribution, Standard};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io(PathBuf, std::io::Error),
+    Gltf(::gltf::Error),
+    MissingBlob,
+    UnsupportedUri(String),
+    MissingBufferData(usize),
+    MissingPositions { mesh: usize, primitive: usize },
+    IndexOutOfRange { mesh: usize, primitive: usize },
+    Mesh(MeshError),
+    NoScene,
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            GltfError::Gltf(e) => write!(f, "{}", e),
+            GltfError::MissingBlob => write!(f, "buffer refers to a missing glb BIN chunk"),
+            GltfError::UnsupportedUri(uri) => write!(f, "unsupported buffer uri `{}`", uri),
+            GltfError::MissingBufferData(i) => write!(f, "buffer {} is too short", i),
+            GltfError::MissingPositions { mesh, primitive } => write!(
+                f,
+                "primitive {} of mesh {} has no POSITION attribute",
+                primitive, mesh
+            ),
+            GltfError::IndexOutOfRange { mesh, primitive } => write!(
+                f,
+                "primitive {} of mesh {} indexes past its vertices",
+                primitive, mesh
+            ),
+            GltfError::Mesh(e) => write!(f, "{}", e),
+            GltfError::NoScene => write!(f, "document contains no scene"),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<::gltf::Error> for GltfError {
+    fn from(e: ::gltf::Error) -> Self {
+        GltfError::Gltf(e)
+    }
+}
+
+impl From<MeshError> for GltfError {
+    fn from(e: MeshError) -> Self {
+        GltfError::Mesh(e)
+    }
+}
+
+// in the terms `Camera::new` takes, v_fov is in degrees
+pub struct GltfCamera<T> {
+    pub origin: Vector3<T>,
+    pub look_at: Vector3<T>,
+    pub up: Vector3<T>,
+    pub v_fov: T,
+    pub aspect: Option<T>,
+}
+
+impl GltfCamera<f64> {
+    // gltf cameras are pinholes, so the aperture is closed and focus distance is arbitrary.
+    // `aspect` is the framebuffer's, when the file's own aspect ratio is wider the field of view
+    // is opened up so everything it framed horizontally still fits
+    pub fn to_camera(&self, aspect: f64) -> Camera<f64> {
+        let v_fov = match self.aspect {
+            Some(file_aspect) if file_aspect > aspect => {
+                let half_height = (self.v_fov.to_radians() / 2.0).tan() * file_aspect / aspect;
+                2.0 * half_height.atan().to_degrees()
+            }
+            _ => self.v_fov,
+        };
+        Camera::new(self.origin, self.look_at, self.up, v_fov, aspect, 0.0, 1.0)
+    }
+}
+
+pub struct GltfScene<T> {
+    pub world: HitTableList<T>,
+    pub camera: Option<GltfCamera<T>>,
+}
+
+fn load_buffers(gltf: &Gltf, base_dir: &Path) -> Result<Vec<Vec<u8>>, GltfError> {
+    let mut buffers = vec![];
+    for buffer in gltf.buffers() {
+        let mut data = match buffer.source() {
+            Source::Bin => gltf.blob.clone().ok_or(GltfError::MissingBlob)?,
+            Source::Uri(uri) if uri.starts_with("data:") || uri.contains("://") => {
+                return Err(GltfError::UnsupportedUri(uri.to_string()))
+            }
+            Source::Uri(uri) => {
+                let path = base_dir.join(uri);
+                std::fs::read(&path).map_err(|e| GltfError::Io(path, e))?
+            }
+        };
+        if data.len() < buffer.length() {
+            return Err(GltfError::MissingBufferData(buffer.index()));
+        }
+        // the glb BIN chunk may be padded to a multiple of four bytes
+        data.truncate(buffer.length());
+        buffers.push(data);
+    }
+    Ok(buffers)
+}
+
+fn convert_material<T>(material: &::gltf::Material) -> Rc<dyn Material<T>>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+{
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+    let base = vec3(r, g, b).cast::<T>().unwrap();
+    let [er, eg, eb] = material.emissive_factor();
+    if er > 0.0 || eg > 0.0 || eb > 0.0 {
+        Rc::new(DiffuseLight::new(vec3(er, eg, eb).cast::<T>().unwrap()))
+    } else if material.alpha_mode() == AlphaMode::Blend && a < 1.0 {
+        Rc::new(Dielectric::new(T::from(1.5).unwrap()))
+    } else if pbr.metallic_factor() >= 0.5 {
+        Rc::new(Metal::new(base, T::from(pbr.roughness_factor()).unwrap()))
+    } else {
+        Rc::new(Lambertian::new(base))
+    }
+}
+
+fn triangulate(mode: Mode, indices: &[usize]) -> Vec<[usize; 3]> {
+    match mode {
+        Mode::Triangles => indices
+            .chunks_exact(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect(),
+        Mode::TriangleStrip => (2..indices.len())
+            .map(|i| {
+                // every other triangle of a strip is wound the other way around
+                if i % 2 == 0 {
+                    [indices[i - 2], indices[i - 1], indices[i]]
+                } else {
+                    [indices[i - 1], indices[i - 2], indices[i]]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan => (2..indices.len())
+            .map(|i| [indices[0], indices[i - 1], indices[i]])
+            .collect(),
+        Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => vec![],
+    }
+}
+
+struct Loader<'a, T> {
+    buffers: &'a [Vec<u8>],
+    materials: Vec<Rc<dyn Material<T>>>,
+    default_material: Rc<dyn Material<T>>,
+    // each mesh is built once and instanced by every node that refers to it
+    meshes: HashMap<usize, Rc<dyn HitTable<T>>>,
+    world: HitTableList<T>,
+    camera: Option<GltfCamera<T>>,
+}
+
+impl<'a, T> Loader<'a, T>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+{
+    fn visit(&mut self, node: Node, parent: &Matrix4<T>) -> Result<(), GltfError> {
+        let local: Matrix4<T> = Matrix4::from(node.transform().matrix()).cast().unwrap();
+        let transform = parent * local;
+
+        if let Some(mesh) = node.mesh() {
+            let object = match self.meshes.get(&mesh.index()) {
+                Some(object) => Rc::clone(object),
+                None => {
+                    let object = self.build_mesh(&mesh)?;
+                    self.meshes.insert(mesh.index(), Rc::clone(&object));
+                    object
+                }
+            };
+            // a node scaled down to nothing can't be seen
+            if let Some(instance) = Instance::new(object, transform) {
+                self.world.add(Box::new(instance));
+            }
+        }
+
+        if let (Some(camera), None) = (node.camera(), &self.camera) {
+            if let ::gltf::camera::Projection::Perspective(p) = camera.projection() {
+                // cameras look down their local -z axis with +y up
+                let origin = transform.w.truncate();
+                let forward = -transform.z.truncate().normalize();
+                self.camera = Some(GltfCamera {
+                    origin,
+                    look_at: origin + forward,
+                    up: transform.y.truncate().normalize(),
+                    v_fov: cgmath::Deg::from(cgmath::Rad(T::from(p.yfov()).unwrap())).0,
+                    aspect: p.aspect_ratio().and_then(T::from),
+                });
+            }
+        }
+
+        for child in node.children() {
+            self.visit(child, &transform)?;
+        }
+        Ok(())
+    }
+
+    fn build_mesh(&self, mesh: &::gltf::Mesh) -> Result<Rc<dyn HitTable<T>>, GltfError> {
+        let mut list = HitTableList::new();
+        for primitive in mesh.primitives() {
+            let buffers = self.buffers;
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| &b[..]));
+            let positions: Vec<Vector3<T>> = reader
+                .read_positions()
+                .ok_or(GltfError::MissingPositions {
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                })?
+                .map(|p| vec3(p[0], p[1], p[2]).cast().unwrap())
+                .collect();
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                None => (0..positions.len()).collect(),
+            };
+            if indices.iter().any(|i| *i >= positions.len()) {
+                return Err(GltfError::IndexOutOfRange {
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                });
+            }
+            let triangles = triangulate(primitive.mode(), &indices);
+            if triangles.is_empty() {
+                continue;
+            }
+
+            let vertex_count = positions.len();
+            let mut data = MeshData::new(positions, triangles);
+            data.normals = reader
+                .read_normals()
+                .map(|normals| {
+                    normals
+                        .map(|n| vec3(n[0], n[1], n[2]).cast().unwrap().normalize())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|normals| normals.len() == vertex_count);
+            data.uvs = reader
+                .read_tex_coords(0)
+                .map(|uvs| {
+                    uvs.into_f32()
+                        .map(|uv| vec2(uv[0], uv[1]).cast().unwrap())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|uvs| uvs.len() == vertex_count);
+
+            let material = match primitive.material().index() {
+                Some(i) => Rc::clone(&self.materials[i]),
+                None => Rc::clone(&self.default_material),
+            };
+            list.add(Box::new(TriangleMesh::new(data, material)?));
+        }
+        Ok(Rc::new(Bvh::from(list)))
+    }
+}
+
+// loads the default scene (or the first one) of a .gltf or .glb file
+pub fn load<T, P>(path: P, default_material: Rc<dyn Material<T>>) -> Result<GltfScene<T>, GltfError>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let gltf = Gltf::open(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let buffers = load_buffers(&gltf, base_dir)?;
+
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or(GltfError::NoScene)?;
+
+    let mut loader = Loader {
+        buffers: &buffers,
+        materials: gltf.materials().map(|m| convert_material(&m)).collect(),
+        default_material,
+        meshes: HashMap::new(),
+        world: HitTableList::new(),
+        camera: None,
+    };
+    for node in scene.nodes() {
+        loader.visit(node, &Matrix4::identity())?;
+    }
+
+    Ok(GltfScene {
+        world: loader.world,
+        camera: loader.camera,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ray;
+
+    // the tangents of the horizontal and vertical half angles a camera covers
+    fn half_extents(camera: &Camera<f64>) -> (f64, f64) {
+        let centre = camera.ray(0.5, 0.5).direction().normalize();
+        let right = camera.ray(1.0, 0.5).direction().normalize();
+        let top = camera.ray(0.5, 1.0).direction().normalize();
+        let tan = |d: Vector3<f64>| d.cross(centre).magnitude() / d.dot(centre);
+        (tan(right), tan(top))
+    }
+
+    fn camera(aspect: Option<f64>) -> GltfCamera<f64> {
+        GltfCamera {
+            origin: vec3(0.0, 0.0, 5.0),
+            look_at: vec3(0.0, 0.0, 0.0),
+            up: vec3(0.0, 1.0, 0.0),
+            v_fov: 60.0,
+            aspect,
+        }
+    }
+
+    #[test]
+    fn cameras_take_the_framebuffer_aspect() {
+        let half_height = 30f64.to_radians().tan();
+        for &file_aspect in &[None, Some(1.0), Some(2.0)] {
+            let (w, h) = half_extents(&camera(file_aspect).to_camera(2.0));
+            assert!((w / h - 2.0).abs() < 1e-9);
+            assert!((h - half_height).abs() < 1e-9);
+        }
+        // a wider file keeps its horizontal framing
+        let (w, h) = half_extents(&camera(Some(4.0)).to_camera(2.0));
+        assert!((w / h - 2.0).abs() < 1e-9);
+        assert!((w - 4.0 * half_height).abs() < 1e-9);
+    }
+
+    #[test]
+    fn loads_meshes_and_cameras() {
+        let dir = std::env::temp_dir().join(format!("gltf-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let positions: Vec<u8> = [0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+            .iter()
+            .flat_map(|f| f.to_le_bytes().to_vec())
+            .collect();
+        std::fs::write(dir.join("triangle.bin"), positions).unwrap();
+        let document = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0, 1] }],
+            "nodes": [
+                { "mesh": 0, "translation": [0, 0, -1] },
+                { "camera": 0, "translation": [0, 0, 5] }
+            ],
+            "cameras": [{
+                "type": "perspective",
+                "perspective": { "yfov": 0.5, "znear": 0.1, "aspectRatio": 1.5 }
+            }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+            "accessors": [{
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [1, 1, 0]
+            }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+            "buffers": [{ "uri": "triangle.bin", "byteLength": 36 }]
+        }"#;
+        std::fs::write(dir.join("triangle.gltf"), document).unwrap();
+
+        let default: Rc<dyn Material<f64>> = Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let scene = load(dir.join("triangle.gltf"), default);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let scene = scene.unwrap();
+
+        let r = Ray::new(vec3(0.2, 0.2, 1.0), vec3(0.0, 0.0, -1.0));
+        let hit = scene.world.hit(&r, 0.001..10.0).unwrap();
+        assert!((hit.get_t() - 2.0).abs() < 1e-6);
+        let miss = Ray::new(vec3(0.8, 0.8, 1.0), vec3(0.0, 0.0, -1.0));
+        assert!(scene.world.hit(&miss, 0.001..10.0).is_none());
+
+        let camera = scene.camera.unwrap();
+        assert!((camera.origin - vec3(0.0, 0.0, 5.0)).magnitude() < 1e-6);
+        assert!((camera.look_at - vec3(0.0, 0.0, 4.0)).magnitude() < 1e-6);
+        assert!((camera.v_fov - 0.5f64.to_degrees()).abs() < 1e-4);
+        assert_eq!(camera.aspect, Some(1.5));
+    }
+}
@@ -0,0 +1,348 @@
+// Density grids come either as .npy files holding a 3D float or uint8 array indexed
+// [x, y, z], or as raw files with this little-endian layout:
+//
+//   u32 nx, u32 ny, u32 nz
+//   nx * ny * nz f32 densities, x varying fastest, then y, then z
+use crate::grid_volume::DensityGrid;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum GridError {
+    Io(std::io::Error),
+    InvalidHeader(String),
+    UnsupportedDtype(String),
+    SizeMismatch { expected: usize, actual: usize },
+    InvalidDensity(f64),
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GridError::Io(e) => write!(f, "{}", e),
+            GridError::InvalidHeader(message) => write!(f, "invalid header: {}", message),
+            GridError::UnsupportedDtype(dtype) => write!(f, "unsupported dtype `{}`", dtype),
+            GridError::SizeMismatch { expected, actual } => write!(
+                f,
+                "expected {} bytes of density data but found {}",
+                expected, actual
+            ),
+            GridError::InvalidDensity(d) => write!(f, "invalid density {}", d),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+impl From<std::io::Error> for GridError {
+    fn from(e: std::io::Error) -> Self {
+        GridError::Io(e)
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn to_grid<T: cgmath::BaseFloat>(
+    resolution: [usize; 3],
+    data: Vec<f64>,
+) -> Result<DensityGrid<T>, GridError> {
+    // densities must be finite and non-negative, which NaN is not
+    if let Some(d) = data.iter().find(|d| !d.is_finite() || **d < 0.0) {
+        return Err(GridError::InvalidDensity(*d));
+    }
+    Ok(DensityGrid::new(
+        resolution,
+        data.into_iter().map(|d| T::from(d).unwrap()).collect(),
+    ))
+}
+
+pub fn parse_raw<T: cgmath::BaseFloat>(bytes: &[u8]) -> Result<DensityGrid<T>, GridError> {
+    if bytes.len() < 12 {
+        return Err(GridError::InvalidHeader("file too short".to_string()));
+    }
+    let resolution = [
+        u32_at(bytes, 0) as usize,
+        u32_at(bytes, 4) as usize,
+        u32_at(bytes, 8) as usize,
+    ];
+    if resolution.contains(&0) {
+        return Err(GridError::InvalidHeader("empty grid".to_string()));
+    }
+    let count = resolution[0] * resolution[1] * resolution[2];
+    if bytes.len() - 12 != count * 4 {
+        return Err(GridError::SizeMismatch {
+            expected: count * 4,
+            actual: bytes.len() - 12,
+        });
+    }
+    let data = (0..count)
+        .map(|i| f64::from(f32::from_bits(u32_at(bytes, 12 + i * 4))))
+        .collect();
+    to_grid(resolution, data)
+}
+
+// pulls the value of `key` out of the python dict literal in an npy header
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, GridError> {
+    let missing = || GridError::InvalidHeader(format!("missing `{}`", key));
+    let start = header.find(&format!("'{}':", key)).ok_or_else(missing)? + key.len() + 3;
+    let rest = header[start..].trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    }
+    .ok_or_else(missing)?;
+    Ok(rest[..end].trim())
+}
+
+pub fn parse_npy<T: cgmath::BaseFloat>(bytes: &[u8]) -> Result<DensityGrid<T>, GridError> {
+    if bytes.len() < 10 || !bytes.starts_with(b"\x93NUMPY") {
+        return Err(GridError::InvalidHeader("not an npy file".to_string()));
+    }
+    let (header_len, header_start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 if bytes.len() >= 12 => (u32_at(bytes, 8) as usize, 12),
+        version => {
+            return Err(GridError::InvalidHeader(format!(
+                "unsupported version {}",
+                version
+            )))
+        }
+    };
+    let data_start = header_start + header_len;
+    let header = bytes
+        .get(header_start..data_start)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| GridError::InvalidHeader("unreadable header".to_string()))?;
+
+    let dtype = header_value(header, "descr")?.trim_matches(|c| c == '\'' || c == '"');
+    let fortran_order = header_value(header, "fortran_order")? == "True";
+    let shape = header_value(header, "shape")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| GridError::InvalidHeader("invalid shape".to_string()))?;
+    if shape.len() != 3 || shape.contains(&0) {
+        return Err(GridError::InvalidHeader(format!(
+            "expected a non-empty 3D array, found shape {:?}",
+            shape
+        )));
+    }
+
+    let size = match dtype.get(1..) {
+        Some("f4") => 4,
+        Some("f8") => 8,
+        Some("u1") => 1,
+        _ => return Err(GridError::UnsupportedDtype(dtype.to_string())),
+    };
+    let big_endian = dtype.starts_with('>');
+    let count = shape[0] * shape[1] * shape[2];
+    let body = &bytes[data_start..];
+    if body.len() != count * size {
+        return Err(GridError::SizeMismatch {
+            expected: count * size,
+            actual: body.len(),
+        });
+    }
+    let value = |i: usize| {
+        let b = &body[i * size..(i + 1) * size];
+        match size {
+            1 => f64::from(b[0]) / 255.0,
+            4 => {
+                let bytes = [b[0], b[1], b[2], b[3]];
+                f64::from(if big_endian {
+                    f32::from_be_bytes(bytes)
+                } else {
+                    f32::from_le_bytes(bytes)
+                })
+            }
+            _ => {
+                let bytes = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
+                if big_endian {
+                    f64::from_be_bytes(bytes)
+                } else {
+                    f64::from_le_bytes(bytes)
+                }
+            }
+        }
+    };
+
+    // fortran order already has x varying fastest, c order has z fastest
+    let resolution = [shape[0], shape[1], shape[2]];
+    let mut data = vec![0.0; count];
+    for z in 0..shape[2] {
+        for y in 0..shape[1] {
+            for x in 0..shape[0] {
+                let i = x + shape[0] * (y + shape[1] * z);
+                let source = if fortran_order {
+                    i
+                } else {
+                    z + shape[2] * (y + shape[1] * x)
+                };
+                data[i] = value(source);
+            }
+        }
+    }
+    to_grid(resolution, data)
+}
+
+// picks the format from the file extension, anything but .npy is read as raw
+pub fn load<T, P>(path: P) -> Result<DensityGrid<T>, GridError>
+where
+    T: cgmath::BaseFloat,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("npy") => parse_npy(&bytes),
+        _ => parse_raw(&bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+
+    const SHAPE: [usize; 3] = [2, 3, 4];
+
+    fn npy_with_dtype(descr: &str, fortran_order: bool, values: &[f32]) -> Vec<u8> {
+        let header = format!(
+            "{{'descr': '{}', 'fortran_order': {}, 'shape': (2, 3, 4), }}\n",
+            descr,
+            if fortran_order { "True" } else { "False" }
+        );
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend((header.len() as u16).to_le_bytes().iter());
+        bytes.extend(header.bytes());
+        for v in values {
+            bytes.extend(v.to_le_bytes().iter());
+        }
+        bytes
+    }
+
+    fn npy(fortran_order: bool, values: &[f32]) -> Vec<u8> {
+        npy_with_dtype("<f4", fortran_order, values)
+    }
+
+    // the density at the center of voxel (x, y, z)
+    fn voxel(grid: &DensityGrid<f64>, x: usize, y: usize, z: usize) -> f64 {
+        let center = |i: usize, n: usize| (i as f64 + 0.5) / n as f64;
+        grid.lookup(vec3(
+            center(x, SHAPE[0]),
+            center(y, SHAPE[1]),
+            center(z, SHAPE[2]),
+        ))
+    }
+
+    fn value(x: usize, y: usize, z: usize) -> f32 {
+        (100 * x + 10 * y + z) as f32
+    }
+
+    #[test]
+    fn npy_arrays_are_indexed_x_y_z() {
+        let mut c_order = vec![];
+        for x in 0..SHAPE[0] {
+            for y in 0..SHAPE[1] {
+                for z in 0..SHAPE[2] {
+                    c_order.push(value(x, y, z));
+                }
+            }
+        }
+        let mut fortran_order = vec![];
+        for z in 0..SHAPE[2] {
+            for y in 0..SHAPE[1] {
+                for x in 0..SHAPE[0] {
+                    fortran_order.push(value(x, y, z));
+                }
+            }
+        }
+        for grid in [
+            parse_npy::<f64>(&npy(false, &c_order)).unwrap(),
+            parse_npy::<f64>(&npy(true, &fortran_order)).unwrap(),
+        ]
+        .iter()
+        {
+            assert_eq!(grid.resolution(), SHAPE);
+            for &(x, y, z) in &[(0, 0, 0), (1, 2, 3), (1, 0, 2), (0, 1, 3)] {
+                assert!((voxel(grid, x, y, z) - f64::from(value(x, y, z))).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn npy_headers_are_checked() {
+        let mut bytes = npy(false, &[0.0; 24]);
+        bytes.truncate(bytes.len() - 4);
+        match parse_npy::<f64>(&bytes) {
+            Err(GridError::SizeMismatch {
+                expected: 96,
+                actual: 92,
+            }) => (),
+            _ => panic!("expected a size mismatch"),
+        }
+        match parse_npy::<f64>(&npy_with_dtype("<i8", false, &[0.0; 24])) {
+            Err(GridError::UnsupportedDtype(dtype)) => assert_eq!(dtype, "<i8"),
+            _ => panic!("expected an unsupported dtype"),
+        }
+        assert!(matches!(
+            parse_npy::<f64>(b"not numpy"),
+            Err(GridError::InvalidHeader(_))
+        ));
+    }
+
+    fn raw(resolution: [u32; 3], values: &[f32]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for n in resolution.iter() {
+            bytes.extend(n.to_le_bytes().iter());
+        }
+        for v in values {
+            bytes.extend(v.to_le_bytes().iter());
+        }
+        bytes
+    }
+
+    #[test]
+    fn raw_sizes_must_match_the_resolution() {
+        let grid = parse_raw::<f64>(&raw([2, 1, 1], &[0.25, 0.5])).unwrap();
+        assert_eq!(grid.resolution(), [2, 1, 1]);
+        assert_eq!(grid.max_density(), 0.5);
+        match parse_raw::<f64>(&raw([2, 2, 1], &[0.25, 0.5])) {
+            Err(GridError::SizeMismatch {
+                expected: 16,
+                actual: 8,
+            }) => (),
+            _ => panic!("expected a size mismatch"),
+        }
+    }
+
+    #[test]
+    fn densities_must_be_finite_and_non_negative() {
+        for &bad in &[-1.0, std::f32::NAN, std::f32::INFINITY] {
+            match parse_raw::<f64>(&raw([2, 1, 1], &[0.5, bad])) {
+                Err(GridError::InvalidDensity(d)) => {
+                    assert!(d.is_nan() == bad.is_nan() && (bad.is_nan() || d == f64::from(bad)))
+                }
+                _ => panic!("expected {} to be rejected", bad),
+            }
+        }
+        let values: Vec<f32> = (0..24)
+            .map(|i| if i == 7 { std::f32::NAN } else { 0.0 })
+            .collect();
+        assert!(matches!(
+            parse_npy::<f64>(&npy(false, &values)),
+            Err(GridError::InvalidDensity(_))
+        ));
+    }
+}
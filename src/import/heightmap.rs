@@ -0,0 +1,152 @@
+// Height maps are grayscale PNGs, 8 or 16 bits per sample, with black the lowest point. Image
+// rows run along z and columns along x. Any alpha channel is ignored.
+use crate::heightfield::HeightMap;
+use image::{ColorType, ImageError};
+use png::HasParameters;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum HeightmapError {
+    Io(std::io::Error),
+    Image(ImageError),
+    UnsupportedColorType(ColorType),
+    TooSmall { width: usize, height: usize },
+}
+
+impl fmt::Display for HeightmapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeightmapError::Io(e) => write!(f, "{}", e),
+            HeightmapError::Image(e) => write!(f, "{}", e),
+            HeightmapError::UnsupportedColorType(color) => write!(
+                f,
+                "expected an 8 or 16 bit grayscale image, found {:?}",
+                color
+            ),
+            HeightmapError::TooSmall { width, height } => write!(
+                f,
+                "height maps need at least 2x2 pixels, found {}x{}",
+                width, height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeightmapError {}
+
+impl From<std::io::Error> for HeightmapError {
+    fn from(e: std::io::Error) -> Self {
+        HeightmapError::Io(e)
+    }
+}
+
+impl From<ImageError> for HeightmapError {
+    fn from(e: ImageError) -> Self {
+        HeightmapError::Image(e)
+    }
+}
+
+pub fn parse_png<T: cgmath::BaseFloat>(bytes: &[u8]) -> Result<HeightMap<T>, HeightmapError> {
+    let mut decoder = png::Decoder::new(bytes);
+    // image's png decoder strips 16 bit samples down to 8, so read the samples untouched
+    decoder.set(png::Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info().map_err(ImageError::from)?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let color = ColorType::from((info.color_type, info.bit_depth));
+    // png stores 16 bit samples big-endian
+    let (channels, bytes_per_sample) = match color {
+        ColorType::Gray(8) => (1, 1),
+        ColorType::Gray(16) => (1, 2),
+        ColorType::GrayA(8) => (2, 1),
+        ColorType::GrayA(16) => (2, 2),
+        _ => return Err(HeightmapError::UnsupportedColorType(color)),
+    };
+    if width < 2 || height < 2 {
+        return Err(HeightmapError::TooSmall { width, height });
+    }
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).map_err(ImageError::from)?;
+    let stride = channels * bytes_per_sample;
+    let data = pixels
+        .chunks(stride)
+        .take(width * height)
+        .map(|pixel| {
+            let sample = if bytes_per_sample == 2 {
+                f64::from(u16::from_be_bytes([pixel[0], pixel[1]])) / 65535.0
+            } else {
+                f64::from(pixel[0]) / 255.0
+            };
+            T::from(sample).unwrap()
+        })
+        .collect();
+    Ok(HeightMap::new([width, height], data))
+}
+
+pub fn load<T, P>(path: P) -> Result<HeightMap<T>, HeightmapError>
+where
+    T: cgmath::BaseFloat,
+    P: AsRef<Path>,
+{
+    parse_png(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::png::PNGEncoder;
+
+    fn png(data: &[u8], width: u32, height: u32, color: ColorType) -> Vec<u8> {
+        let mut bytes = vec![];
+        PNGEncoder::new(&mut bytes)
+            .encode(data, width, height, color)
+            .unwrap();
+        bytes
+    }
+
+    fn check(map: HeightMap<f64>, expected: &[f64]) {
+        assert_eq!(map.resolution(), [3, 2]);
+        for (i, height) in expected.iter().enumerate() {
+            assert!((map.get(i % 3, i / 3) - height).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn decodes_8_bit_grayscale() {
+        let data = [0, 51, 255, 102, 153, 204];
+        let map = parse_png(&png(&data, 3, 2, ColorType::Gray(8))).unwrap();
+        check(map, &[0.0, 0.2, 1.0, 0.4, 0.6, 0.8]);
+
+        // alpha is ignored
+        let data = [0, 9, 51, 9, 255, 9, 102, 9, 153, 9, 204, 9];
+        let map = parse_png(&png(&data, 3, 2, ColorType::GrayA(8))).unwrap();
+        check(map, &[0.0, 0.2, 1.0, 0.4, 0.6, 0.8]);
+    }
+
+    #[test]
+    fn decodes_16_bit_grayscale() {
+        let samples: [u16; 6] = [0, 1, 0x1234, 65535, 32768, 65534];
+        let data: Vec<u8> = samples
+            .iter()
+            .flat_map(|s| s.to_be_bytes().to_vec())
+            .collect();
+        let map = parse_png(&png(&data, 3, 2, ColorType::Gray(16))).unwrap();
+        let expected: Vec<f64> = samples.iter().map(|s| f64::from(*s) / 65535.0).collect();
+        check(map, &expected);
+    }
+
+    #[test]
+    fn rejects_color_and_tiny_images() {
+        match parse_png::<f64>(&png(&[0; 18], 3, 2, ColorType::RGB(8))) {
+            Err(HeightmapError::UnsupportedColorType(ColorType::RGB(8))) => (),
+            _ => panic!("expected rgb to be rejected"),
+        }
+        match parse_png::<f64>(&png(&[0; 3], 3, 1, ColorType::Gray(8))) {
+            Err(HeightmapError::TooSmall {
+                width: 3,
+                height: 1,
+            }) => (),
+            _ => panic!("expected a single row to be rejected"),
+        }
+    }
+}
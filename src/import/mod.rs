@@ -0,0 +1,16 @@
+use crate::MeshData;
+
+pub mod gltf;
+pub mod grid;
+pub mod heightmap;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+// mesh formats without materials. `degenerate` lists the faces, numbered as in the file, that
+// had zero-area triangles dropped. polygons are split into fans so a face may only lose part of
+// itself
+pub struct MeshImport<T> {
+    pub mesh: MeshData<T>,
+    pub degenerate: Vec<usize>,
+}
@@ -0,0 +1,582 @@
+use crate::{
+    Dielectric, HitTableList, Lambertian, Material, MeshData, MeshError, Metal, TriangleMesh,
+};
+use cgmath::{vec2, vec3, Vector2, Vector3};
+use rand::distributions::{Distribution, Standard};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub enum ParseErrorKind {
+    MissingValue(&'static str),
+    InvalidNumber(String),
+    InvalidIndex(String),
+    IndexOutOfRange(isize),
+    TooFewVertices(usize),
+    UnknownMaterial(String),
+    MissingMaterialLibrary(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Obj(ParseError),
+    Mtl(PathBuf, ParseError),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::MissingValue(what) => write!(f, "missing {}", what),
+            ParseErrorKind::InvalidNumber(s) => write!(f, "invalid number `{}`", s),
+            ParseErrorKind::InvalidIndex(s) => write!(f, "invalid vertex reference `{}`", s),
+            ParseErrorKind::IndexOutOfRange(i) => write!(f, "index {} out of range", i),
+            ParseErrorKind::TooFewVertices(n) => write!(f, "face with only {} vertices", n),
+            ParseErrorKind::UnknownMaterial(name) => write!(f, "unknown material `{}`", name),
+            ParseErrorKind::MissingMaterialLibrary(name) => {
+                write!(f, "material library `{}` not found", name)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ObjError::Obj(e) => write!(f, "obj {}", e),
+            ObjError::Mtl(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+pub struct ObjGroup<T> {
+    pub name: String,
+    pub material: Option<Rc<dyn Material<T>>>,
+    pub mesh: MeshData<T>,
+}
+
+pub struct Obj<T> {
+    pub groups: Vec<ObjGroup<T>>,
+}
+
+impl<T: cgmath::BaseFloat + 'static> Obj<T> {
+    // groups without a `usemtl` get `default`
+    pub fn into_hit_table_list(
+        self,
+        default: Rc<dyn Material<T>>,
+    ) -> Result<HitTableList<T>, MeshError> {
+        let mut list = HitTableList::new();
+        for group in self.groups {
+            let material = group.material.unwrap_or_else(|| Rc::clone(&default));
+            list.add(Box::new(TriangleMesh::new(group.mesh, material)?));
+        }
+        Ok(list)
+    }
+}
+
+type Materials<T> = HashMap<String, Rc<dyn Material<T>>>;
+
+fn parse_float<T: cgmath::BaseFloat>(
+    token: Option<&str>,
+    what: &'static str,
+) -> Result<T, ParseErrorKind> {
+    let token = token.ok_or(ParseErrorKind::MissingValue(what))?;
+    token
+        .parse::<f64>()
+        .ok()
+        .and_then(T::from)
+        .ok_or_else(|| ParseErrorKind::InvalidNumber(token.to_string()))
+}
+
+fn parse_vec3<'a, T, I>(tokens: &mut I, what: &'static str) -> Result<Vector3<T>, ParseErrorKind>
+where
+    T: cgmath::BaseFloat,
+    I: Iterator<Item = &'a str>,
+{
+    Ok(vec3(
+        parse_float(tokens.next(), what)?,
+        parse_float(tokens.next(), what)?,
+        parse_float(tokens.next(), what)?,
+    ))
+}
+
+// obj indices are 1-based, negative values count back from the most recent element
+fn resolve_index(token: &str, len: usize) -> Result<usize, ParseErrorKind> {
+    let i = token
+        .parse::<isize>()
+        .map_err(|_| ParseErrorKind::InvalidIndex(token.to_string()))?;
+    let resolved = if i < 0 { len as isize + i } else { i - 1 };
+    if i == 0 || resolved < 0 || resolved >= len as isize {
+        Err(ParseErrorKind::IndexOutOfRange(i))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+fn from_mtl<T>(
+    diffuse: Vector3<T>,
+    specular: Vector3<T>,
+    shininess: T,
+    ior: T,
+    dissolve: T,
+    illum: u32,
+) -> Rc<dyn Material<T>>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+{
+    let zero = T::zero();
+    let one = T::one();
+    let transparent = dissolve < one || [4, 6, 7, 9].contains(&illum);
+    let reflective = [3, 5, 8].contains(&illum)
+        || (specular != vec3(zero, zero, zero) && diffuse == vec3(zero, zero, zero));
+    if transparent {
+        Rc::new(Dielectric::new(ior))
+    } else if reflective {
+        // Blinn-Phong exponent to a roughness, which stands in for the fuzz factor
+        let fuzz = (T::from(2.0).unwrap() / (shininess + T::from(2.0).unwrap())).sqrt();
+        Rc::new(Metal::new(specular, fuzz.min(one)))
+    } else {
+        Rc::new(Lambertian::new(diffuse))
+    }
+}
+
+fn parse_mtl<T, R>(reader: R, path: &Path) -> Result<Materials<T>, ObjError>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+    R: BufRead,
+{
+    struct Entry<T> {
+        name: String,
+        diffuse: Vector3<T>,
+        specular: Vector3<T>,
+        shininess: T,
+        ior: T,
+        dissolve: T,
+        illum: u32,
+    }
+
+    let mut materials = HashMap::new();
+    let mut current: Option<Entry<T>> = None;
+    let mut finish = |entry: Option<Entry<T>>| {
+        if let Some(e) = entry {
+            let material = from_mtl(
+                e.diffuse,
+                e.specular,
+                e.shininess,
+                e.ior,
+                e.dissolve,
+                e.illum,
+            );
+            materials.insert(e.name, material);
+        }
+    };
+
+    for (i, line) in reader.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+        let mut tokens = line.split_whitespace();
+        let result = match tokens.next() {
+            Some("newmtl") => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                if name.is_empty() {
+                    Err(ParseErrorKind::MissingValue("material name"))
+                } else {
+                    let zero = T::zero();
+                    finish(current.take());
+                    current = Some(Entry {
+                        name,
+                        diffuse: vec3(zero, zero, zero),
+                        specular: vec3(zero, zero, zero),
+                        shininess: zero,
+                        ior: T::one(),
+                        dissolve: T::one(),
+                        illum: 2,
+                    });
+                    Ok(())
+                }
+            }
+            Some(statement) => match current.as_mut() {
+                None => Ok(()),
+                Some(entry) => match statement {
+                    "Kd" => parse_vec3(&mut tokens, "Kd").map(|v| entry.diffuse = v),
+                    "Ks" => parse_vec3(&mut tokens, "Ks").map(|v| entry.specular = v),
+                    "Ns" => parse_float(tokens.next(), "Ns").map(|v| entry.shininess = v),
+                    "Ni" => parse_float(tokens.next(), "Ni").map(|v| entry.ior = v),
+                    "d" => parse_float(tokens.next(), "d").map(|v| entry.dissolve = v),
+                    "Tr" => {
+                        parse_float(tokens.next(), "Tr").map(|v: T| entry.dissolve = T::one() - v)
+                    }
+                    "illum" => match tokens.next() {
+                        None => Err(ParseErrorKind::MissingValue("illum")),
+                        Some(token) => token
+                            .parse()
+                            .map(|v| entry.illum = v)
+                            .map_err(|_| ParseErrorKind::InvalidNumber(token.to_string())),
+                    },
+                    _ => Ok(()),
+                },
+            },
+            None => Ok(()),
+        };
+        result.map_err(|kind| {
+            ObjError::Mtl(
+                path.to_path_buf(),
+                ParseError {
+                    line: line_number,
+                    kind,
+                },
+            )
+        })?;
+    }
+    finish(current.take());
+    Ok(materials)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+struct GroupBuilder {
+    name: String,
+    material: Option<String>,
+    vertices: HashMap<VertexKey, usize>,
+    keys: Vec<VertexKey>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl GroupBuilder {
+    fn new(name: String, material: Option<String>) -> Self {
+        Self {
+            name,
+            material,
+            vertices: HashMap::new(),
+            keys: vec![],
+            indices: vec![],
+        }
+    }
+
+    fn vertex(&mut self, key: VertexKey) -> usize {
+        let keys = &mut self.keys;
+        *self.vertices.entry(key).or_insert_with(|| {
+            keys.push(key);
+            keys.len() - 1
+        })
+    }
+
+    // attributes are only kept when every vertex of the group has them
+    fn build<T: cgmath::BaseFloat>(
+        self,
+        positions: &[Vector3<T>],
+        uvs: &[Vector2<T>],
+        normals: &[Vector3<T>],
+        materials: &Materials<T>,
+    ) -> ObjGroup<T> {
+        let mut mesh = MeshData::new(
+            self.keys.iter().map(|k| positions[k.position]).collect(),
+            self.indices,
+        );
+        mesh.uvs = self
+            .keys
+            .iter()
+            .map(|k| k.uv.map(|i| uvs[i]))
+            .collect::<Option<Vec<_>>>();
+        mesh.normals = self
+            .keys
+            .iter()
+            .map(|k| k.normal.map(|i| normals[i]))
+            .collect::<Option<Vec<_>>>();
+        ObjGroup {
+            name: self.name,
+            material: self
+                .material
+                .and_then(|name| materials.get(&name).map(Rc::clone)),
+            mesh,
+        }
+    }
+}
+
+// `path` is only used to resolve `mtllib` statements and to report errors
+pub fn parse<T, R>(reader: R, path: &Path) -> Result<Obj<T>, ObjError>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+    R: BufRead,
+{
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut positions = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut materials = HashMap::new();
+    let mut groups = vec![];
+    let mut current = GroupBuilder::new("default".to_string(), None);
+
+    for (i, line) in reader.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+        let mut tokens = line.split_whitespace();
+        let result = match tokens.next() {
+            Some("v") => parse_vec3(&mut tokens, "vertex position").map(|v| positions.push(v)),
+            Some("vn") => parse_vec3(&mut tokens, "vertex normal").map(|v| normals.push(v)),
+            Some("vt") => parse_float(tokens.next(), "texture coordinate").and_then(|u| {
+                // v is optional for 1D textures
+                let v = tokens.next().map_or(Ok(T::zero()), |t| {
+                    parse_float(Some(t), "texture coordinate")
+                })?;
+                uvs.push(vec2(u, v));
+                Ok(())
+            }),
+            Some("f") => {
+                let mut face = vec![];
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let position = resolve_index(parts.next().unwrap_or(""), positions.len());
+                    let uv = match parts.next() {
+                        None | Some("") => Ok(None),
+                        Some(t) => resolve_index(t, uvs.len()).map(Some),
+                    };
+                    let normal = match parts.next() {
+                        None | Some("") => Ok(None),
+                        Some(t) => resolve_index(t, normals.len()).map(Some),
+                    };
+                    match (position, uv, normal) {
+                        (Ok(position), Ok(uv), Ok(normal)) => {
+                            face.push(current.vertex(VertexKey {
+                                position,
+                                uv,
+                                normal,
+                            }))
+                        }
+                        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                            return Err(ObjError::Obj(ParseError {
+                                line: line_number,
+                                kind: e,
+                            }))
+                        }
+                    }
+                }
+                if face.len() < 3 {
+                    Err(ParseErrorKind::TooFewVertices(face.len()))
+                } else {
+                    for j in 1..face.len() - 1 {
+                        current.indices.push([face[0], face[j], face[j + 1]]);
+                    }
+                    Ok(())
+                }
+            }
+            Some("g") | Some("o") => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                let material = current.material.clone();
+                let previous = std::mem::replace(&mut current, GroupBuilder::new(name, material));
+                groups.push(previous);
+                Ok(())
+            }
+            Some("usemtl") => {
+                let name = tokens.collect::<Vec<_>>().join(" ");
+                if !materials.contains_key(&name) {
+                    Err(ParseErrorKind::UnknownMaterial(name))
+                } else {
+                    let group_name = current.name.clone();
+                    let previous =
+                        std::mem::replace(&mut current, GroupBuilder::new(group_name, Some(name)));
+                    groups.push(previous);
+                    Ok(())
+                }
+            }
+            Some("mtllib") => {
+                let mut result = Ok(());
+                for name in tokens {
+                    let path = base_dir.join(name);
+                    let file = match File::open(&path) {
+                        Ok(file) => file,
+                        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
+                            result = Err(ParseErrorKind::MissingMaterialLibrary(name.to_string()));
+                            break;
+                        }
+                        Err(e) => return Err(ObjError::Io(path, e)),
+                    };
+                    materials.extend(parse_mtl(BufReader::new(file), &path)?);
+                }
+                result
+            }
+            _ => Ok(()),
+        };
+        result.map_err(|kind| {
+            ObjError::Obj(ParseError {
+                line: line_number,
+                kind,
+            })
+        })?;
+    }
+    groups.push(current);
+
+    Ok(Obj {
+        groups: groups
+            .into_iter()
+            .filter(|g| !g.indices.is_empty())
+            .map(|g| g.build(&positions, &uvs, &normals, &materials))
+            .collect(),
+    })
+}
+
+pub fn load<T, P>(path: P) -> Result<Obj<T>, ObjError>
+where
+    T: cgmath::BaseFloat + 'static,
+    Standard: Distribution<T>,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    parse(BufReader::new(file), path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(source: &str) -> Result<Obj<f64>, ObjError> {
+        parse(source.as_bytes(), Path::new("/nonexistent/test.obj"))
+    }
+
+    fn parse_error(source: &str) -> ParseError {
+        match parse_str(source) {
+            Err(ObjError::Obj(e)) => e,
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    #[test]
+    fn face_indices_must_refer_to_existing_vertices() {
+        assert_eq!(
+            parse_error(&format!("{}f 1 2 4\n", TRIANGLE)),
+            ParseError {
+                line: 4,
+                kind: ParseErrorKind::IndexOutOfRange(4),
+            }
+        );
+        assert_eq!(
+            parse_error(&format!("{}f 0 1 2\n", TRIANGLE)),
+            ParseError {
+                line: 4,
+                kind: ParseErrorKind::IndexOutOfRange(0),
+            }
+        );
+        assert_eq!(
+            parse_error(&format!("{}f 1 2 -4\n", TRIANGLE)),
+            ParseError {
+                line: 4,
+                kind: ParseErrorKind::IndexOutOfRange(-4),
+            }
+        );
+    }
+
+    #[test]
+    fn faces_need_three_vertices() {
+        assert_eq!(
+            parse_error(&format!("{}\nf 1 2\n", TRIANGLE)),
+            ParseError {
+                line: 5,
+                kind: ParseErrorKind::TooFewVertices(2),
+            }
+        );
+    }
+
+    #[test]
+    fn positions_must_be_numbers() {
+        assert_eq!(
+            parse_error("v 0 0 0\nv 1 x 0\n"),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::InvalidNumber("x".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_error("v 0 0\n"),
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::MissingValue("vertex position"),
+            }
+        );
+    }
+
+    #[test]
+    fn materials_must_be_defined_before_use() {
+        assert_eq!(
+            parse_error(&format!("{}usemtl shiny\nf 1 2 3\n", TRIANGLE)),
+            ParseError {
+                line: 4,
+                kind: ParseErrorKind::UnknownMaterial("shiny".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_error("# header\nmtllib missing.mtl\n"),
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::MissingMaterialLibrary("missing.mtl".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn groups_uvs_and_normals_are_kept() {
+        let source = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+g first
+f 1/1/1 2/2/1 3/3/1 4/4/1
+g second
+f 1 2 3
+";
+        let obj = parse_str(source).ok().unwrap();
+        assert_eq!(obj.groups.len(), 2);
+
+        let first = &obj.groups[0];
+        assert_eq!(first.name, "first");
+        assert_eq!(first.mesh.triangle_count(), 2);
+        assert_eq!(first.mesh.positions.len(), 4);
+        let uvs = first.mesh.uvs.as_ref().unwrap();
+        let normals = first.mesh.normals.as_ref().unwrap();
+        for (i, position) in first.mesh.positions.iter().enumerate() {
+            assert_eq!(uvs[i], vec2(position.x, position.y));
+            assert_eq!(normals[i], vec3(0.0, 0.0, 1.0));
+        }
+
+        let second = &obj.groups[1];
+        assert_eq!(second.name, "second");
+        assert_eq!(second.mesh.triangle_count(), 1);
+        assert!(second.mesh.uvs.is_none() && second.mesh.normals.is_none());
+    }
+}
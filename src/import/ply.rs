@@ -0,0 +1,502 @@
+use super::MeshImport;
+use crate::MeshData;
+use cgmath::{vec2, vec3};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(std::io::Error),
+    Header { line: usize, message: String },
+    UnexpectedEof { element: String },
+    InvalidValue { element: String, token: String },
+    MissingVertexProperty(&'static str),
+    IndexOutOfRange { face: usize, index: i64 },
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlyError::Io(e) => write!(f, "{}", e),
+            PlyError::Header { line, message } => write!(f, "header line {}: {}", line, message),
+            PlyError::UnexpectedEof { element } => {
+                write!(f, "unexpected end of file in `{}` data", element)
+            }
+            PlyError::InvalidValue { element, token } => {
+                write!(f, "invalid value `{}` in `{}` data", token, element)
+            }
+            PlyError::MissingVertexProperty(name) => {
+                write!(f, "vertex element has no `{}` property", name)
+            }
+            PlyError::IndexOutOfRange { face, index } => {
+                write!(f, "face {} refers to missing vertex {}", face, index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+impl From<std::io::Error> for PlyError {
+    fn from(e: std::io::Error) -> Self {
+        PlyError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // integer colors span the full range of their type, float colors are already in [0, 1]
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.0,
+            Scalar::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+
+    fn decode(self, bytes: &[u8], format: Format) -> f64 {
+        macro_rules! read {
+            ($t:ty) => {{
+                let mut buf = [0u8; std::mem::size_of::<$t>()];
+                buf.copy_from_slice(bytes);
+                if format == Format::BinaryBigEndian {
+                    <$t>::from_be_bytes(buf) as f64
+                } else {
+                    <$t>::from_le_bytes(buf) as f64
+                }
+            }};
+        }
+        match self {
+            Scalar::I8 => read!(i8),
+            Scalar::U8 => read!(u8),
+            Scalar::I16 => read!(i16),
+            Scalar::U16 => read!(u16),
+            Scalar::I32 => read!(i32),
+            Scalar::U32 => read!(u32),
+            Scalar::F32 => read!(f32),
+            Scalar::F64 => read!(f64),
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> Result<(Format, Vec<Element>), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut line_number = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(PlyError::Header {
+                line: line_number,
+                message: "missing end_header".to_string(),
+            });
+        }
+        line_number += 1;
+        let error = |message: &str| PlyError::Header {
+            line: line_number,
+            message: message.to_string(),
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(error("not a ply file"));
+            }
+            continue;
+        }
+        match tokens.as_slice() {
+            ["format", f, _version] => {
+                format = Some(match *f {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error("unknown format")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| error("invalid element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before element"))?;
+                if element.name == "vertex" {
+                    return Err(error("list property on vertex element"));
+                }
+                let count = Scalar::parse(count).ok_or_else(|| error("unknown type"))?;
+                let item = Scalar::parse(item).ok_or_else(|| error("unknown type"))?;
+                element
+                    .properties
+                    .push(Property::List(name.to_string(), count, item));
+            }
+            ["property", ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before element"))?;
+                let ty = Scalar::parse(ty).ok_or_else(|| error("unknown type"))?;
+                element
+                    .properties
+                    .push(Property::Scalar(name.to_string(), ty));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => (),
+            ["end_header"] => break,
+            _ => return Err(error("unrecognized header line")),
+        }
+    }
+    let format = format.ok_or(PlyError::Header {
+        line: line_number,
+        message: "missing format".to_string(),
+    })?;
+    Ok((format, elements))
+}
+
+// yields each property of an element as a list of values, scalars being lists of one
+struct ValueReader<R> {
+    reader: R,
+    format: Format,
+    line: std::vec::IntoIter<String>,
+}
+
+impl<R: BufRead> ValueReader<R> {
+    fn next(&mut self, ty: Scalar, element: &str) -> Result<f64, PlyError> {
+        let eof = || PlyError::UnexpectedEof {
+            element: element.to_string(),
+        };
+        if self.format == Format::Ascii {
+            let token = loop {
+                if let Some(token) = self.line.next() {
+                    break token;
+                }
+                let mut line = String::new();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Err(eof());
+                }
+                self.line = line
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+                    .into_iter();
+            };
+            token.parse().map_err(|_| PlyError::InvalidValue {
+                element: element.to_string(),
+                token,
+            })
+        } else {
+            let mut buf = [0u8; 8];
+            let bytes = &mut buf[..ty.size()];
+            self.reader.read_exact(bytes).map_err(|e| {
+                if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                    eof()
+                } else {
+                    PlyError::Io(e)
+                }
+            })?;
+            Ok(ty.decode(bytes, self.format))
+        }
+    }
+
+    fn property(&mut self, property: &Property, element: &str) -> Result<Vec<f64>, PlyError> {
+        match property {
+            Property::Scalar(_, ty) => Ok(vec![self.next(*ty, element)?]),
+            Property::List(_, count, item) => {
+                let count = self.next(*count, element)? as usize;
+                (0..count).map(|_| self.next(*item, element)).collect()
+            }
+        }
+    }
+}
+
+pub fn parse<T, R>(mut reader: R) -> Result<MeshImport<T>, PlyError>
+where
+    T: cgmath::BaseFloat,
+    R: BufRead,
+{
+    let (format, elements) = read_header(&mut reader)?;
+    let mut values = ValueReader {
+        reader,
+        format,
+        line: vec![].into_iter(),
+    };
+
+    let mut vertices: Vec<Vec<f64>> = vec![];
+    let mut vertex_properties: Vec<(String, Scalar)> = vec![];
+    let mut faces: Vec<Vec<f64>> = vec![];
+    for element in elements.iter() {
+        let face_property = element
+            .properties
+            .iter()
+            .position(|p| p.name() == "vertex_indices" || p.name() == "vertex_index");
+        for _ in 0..element.count {
+            let mut row = vec![];
+            for (i, property) in element.properties.iter().enumerate() {
+                let value = values.property(property, &element.name)?;
+                match element.name.as_str() {
+                    "vertex" => row.push(value[0]),
+                    "face" if Some(i) == face_property => faces.push(value),
+                    _ => (),
+                }
+            }
+            if element.name == "vertex" {
+                vertices.push(row);
+            }
+        }
+        if element.name == "vertex" {
+            vertex_properties = element
+                .properties
+                .iter()
+                .map(|p| match p {
+                    Property::Scalar(name, ty) => (name.clone(), *ty),
+                    Property::List(name, _, ty) => (name.clone(), *ty),
+                })
+                .collect();
+        }
+    }
+
+    let column = |name: &str| vertex_properties.iter().position(|(n, _)| n == name);
+    let columns = |names: [&str; 3]| -> Option<[usize; 3]> {
+        Some([column(names[0])?, column(names[1])?, column(names[2])?])
+    };
+    let to_t = |v: f64| T::from(v).unwrap();
+
+    let required = |name: &'static str| column(name).ok_or(PlyError::MissingVertexProperty(name));
+    let (x, y, z) = (required("x")?, required("y")?, required("z")?);
+    let positions = vertices
+        .iter()
+        .map(|v| vec3(to_t(v[x]), to_t(v[y]), to_t(v[z])))
+        .collect::<Vec<_>>();
+
+    let mut indices = vec![];
+    // the face each triangle of the fans below came from
+    let mut triangle_faces = vec![];
+    for (face, polygon) in faces.iter().enumerate() {
+        let polygon = polygon
+            .iter()
+            .map(|i| {
+                let index = *i as i64;
+                if index < 0 || index as usize >= positions.len() {
+                    Err(PlyError::IndexOutOfRange { face, index })
+                } else {
+                    Ok(index as usize)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for j in 2..polygon.len() {
+            indices.push([polygon[0], polygon[j - 1], polygon[j]]);
+            triangle_faces.push(face);
+        }
+    }
+
+    let mut mesh = MeshData::new(positions, indices);
+    mesh.normals = columns(["nx", "ny", "nz"]).map(|[nx, ny, nz]| {
+        vertices
+            .iter()
+            .map(|v| vec3(to_t(v[nx]), to_t(v[ny]), to_t(v[nz])))
+            .collect()
+    });
+    mesh.uvs = column("u")
+        .zip(column("v"))
+        .or_else(|| column("s").zip(column("t")))
+        .or_else(|| column("texture_u").zip(column("texture_v")))
+        .map(|(u, v)| {
+            vertices
+                .iter()
+                .map(|vertex| vec2(to_t(vertex[u]), to_t(vertex[v])))
+                .collect()
+        });
+    mesh.colors = columns(["red", "green", "blue"]).map(|[r, g, b]| {
+        let scale = vertex_properties[r].1.color_scale();
+        vertices
+            .iter()
+            .map(|v| vec3(to_t(v[r] / scale), to_t(v[g] / scale), to_t(v[b] / scale)))
+            .collect()
+    });
+
+    let mut degenerate: Vec<usize> = mesh
+        .remove_degenerate()
+        .into_iter()
+        .map(|triangle| triangle_faces[triangle])
+        .collect();
+    degenerate.dedup();
+    Ok(MeshImport { mesh, degenerate })
+}
+
+pub fn load<T, P>(path: P) -> Result<MeshImport<T>, PlyError>
+where
+    T: cgmath::BaseFloat,
+    P: AsRef<Path>,
+{
+    parse(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [2.0, 0.0, 0.0],
+    ];
+    // a quad and a triangle whose corners are all on one line
+    const FACES: [&[i32]; 2] = [&[0, 1, 2, 3], &[0, 1, 4]];
+
+    fn header(format: &str) -> String {
+        format!(
+            "ply\nformat {} 1.0\ncomment a quad\nelement vertex 5\nproperty float x\n\
+             property float y\nproperty float z\nelement face 2\n\
+             property list uchar int vertex_indices\nend_header\n",
+            format
+        )
+    }
+
+    fn binary(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = header(format).into_bytes();
+        for c in POSITIONS.iter().flatten() {
+            let word = if big_endian {
+                c.to_be_bytes()
+            } else {
+                c.to_le_bytes()
+            };
+            bytes.extend(word.iter());
+        }
+        for face in FACES.iter() {
+            bytes.push(face.len() as u8);
+            for i in face.iter() {
+                let word = if big_endian {
+                    i.to_be_bytes()
+                } else {
+                    i.to_le_bytes()
+                };
+                bytes.extend(word.iter());
+            }
+        }
+        bytes
+    }
+
+    fn check(import: MeshImport<f64>) {
+        let positions: Vec<_> = POSITIONS
+            .iter()
+            .map(|p| vec3(p[0], p[1], p[2]).cast().unwrap())
+            .collect();
+        assert_eq!(import.mesh.positions, positions);
+        assert_eq!(import.mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(import.degenerate, vec![1]);
+    }
+
+    #[test]
+    fn reads_ascii() {
+        let mut text = header("ascii");
+        for p in POSITIONS.iter() {
+            text += &format!("{} {} {}\n", p[0], p[1], p[2]);
+        }
+        text += "4 0 1 2 3\n3 0 1 4\n";
+        check(parse(text.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_in_both_byte_orders() {
+        check(parse(&binary("binary_little_endian", false)[..]).unwrap());
+        check(parse(&binary("binary_big_endian", true)[..]).unwrap());
+    }
+
+    #[test]
+    fn degenerate_triangles_are_reported_by_face() {
+        let mut text = header("ascii").replace("element face 2", "element face 3");
+        for p in POSITIONS.iter() {
+            text += &format!("{} {} {}\n", p[0], p[1], p[2]);
+        }
+        // the second face's fan has one flat triangle and one good one
+        text += "3 0 1 2\n5 0 1 4 2 3\n3 1 4 0\n";
+        let import = parse::<f64, _>(text.as_bytes()).unwrap();
+        assert_eq!(import.mesh.triangle_count(), 3);
+        assert_eq!(import.degenerate, vec![1, 2]);
+    }
+
+    #[test]
+    fn rejects_list_properties_on_vertices() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+                    property list uchar float extra\nend_header\n1 0\n";
+        match parse::<f64, _>(text.as_bytes()) {
+            Err(PlyError::Header { line: 5, .. }) => (),
+            _ => panic!("expected a header error on line 5"),
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let mut text = header("ascii");
+        for p in POSITIONS.iter() {
+            text += &format!("{} {} {}\n", p[0], p[1], p[2]);
+        }
+        text += "3 0 1 2\n3 0 1 5\n";
+        match parse::<f64, _>(text.as_bytes()) {
+            Err(PlyError::IndexOutOfRange { face: 1, index: 5 }) => (),
+            _ => panic!("expected an out of range index"),
+        }
+    }
+}
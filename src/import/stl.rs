@@ -0,0 +1,224 @@
+use super::MeshImport;
+use crate::MeshData;
+use cgmath::{vec3, Vector3};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    Truncated { expected: usize, actual: usize },
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StlError::Io(e) => write!(f, "{}", e),
+            StlError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            StlError::Truncated { expected, actual } => write!(
+                f,
+                "binary stl should be {} bytes but is {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StlError {}
+
+impl From<std::io::Error> for StlError {
+    fn from(e: std::io::Error) -> Self {
+        StlError::Io(e)
+    }
+}
+
+// stl stores unconnected triangles, so corners are welded back together by exact position
+struct Welder {
+    vertices: HashMap<[u32; 3], usize>,
+    positions: Vec<[f32; 3]>,
+}
+
+impl Welder {
+    fn vertex(&mut self, p: [f32; 3]) -> usize {
+        // +0.0 and -0.0 are the same point
+        let key = [
+            (p[0] + 0.0).to_bits(),
+            (p[1] + 0.0).to_bits(),
+            (p[2] + 0.0).to_bits(),
+        ];
+        let positions = &mut self.positions;
+        *self.vertices.entry(key).or_insert_with(|| {
+            positions.push(p);
+            positions.len() - 1
+        })
+    }
+}
+
+fn to_mesh<T: cgmath::BaseFloat>(triangles: Vec<[[f32; 3]; 3]>) -> MeshImport<T> {
+    let mut welder = Welder {
+        vertices: HashMap::new(),
+        positions: vec![],
+    };
+    let indices = triangles
+        .into_iter()
+        .map(|[a, b, c]| [welder.vertex(a), welder.vertex(b), welder.vertex(c)])
+        .collect();
+    let positions: Vec<Vector3<T>> = welder
+        .positions
+        .iter()
+        .map(|p| vec3(p[0], p[1], p[2]).cast().unwrap())
+        .collect();
+    let mut mesh = MeshData::new(positions, indices);
+    let degenerate = mesh.remove_degenerate();
+    MeshImport { mesh, degenerate }
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<[[f32; 3]; 3]>, StlError> {
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let expected = 84 + count * 50;
+    if bytes.len() < expected {
+        return Err(StlError::Truncated {
+            expected,
+            actual: bytes.len(),
+        });
+    }
+    let float = |offset: usize| {
+        f32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    };
+    // each record is a facet normal, three corners and a two byte attribute count
+    Ok((0..count)
+        .map(|i| {
+            let record = 84 + i * 50 + 12;
+            let corner = |c: usize| {
+                let offset = record + c * 12;
+                [float(offset), float(offset + 4), float(offset + 8)]
+            };
+            [corner(0), corner(1), corner(2)]
+        })
+        .collect())
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<[[f32; 3]; 3]>, StlError> {
+    let mut triangles = vec![];
+    let mut corners = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: &str| StlError::Parse {
+            line: i + 1,
+            message: message.to_string(),
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["vertex", x, y, z] => {
+                let parse = |t: &str| t.parse::<f32>().map_err(|_| error("invalid coordinate"));
+                corners.push([parse(x)?, parse(y)?, parse(z)?]);
+            }
+            ["vertex", ..] => return Err(error("vertex needs three coordinates")),
+            ["endloop"] => {
+                if corners.len() != 3 {
+                    return Err(error("facet loop must have exactly three vertices"));
+                }
+                triangles.push([corners[0], corners[1], corners[2]]);
+                corners.clear();
+            }
+            _ => (),
+        }
+    }
+    Ok(triangles)
+}
+
+pub fn parse<T: cgmath::BaseFloat>(bytes: &[u8]) -> Result<MeshImport<T>, StlError> {
+    // binary files may also start with "solid", so trust the size implied by the header instead
+    let binary = bytes.len() >= 84 && {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        !bytes.starts_with(b"solid") || bytes.len() == 84 + count * 50
+    };
+    let triangles = if binary {
+        parse_binary(bytes)?
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| StlError::Parse {
+            line: 0,
+            message: "ascii stl is not valid utf-8".to_string(),
+        })?;
+        parse_ascii(text)?
+    };
+    Ok(to_mesh(triangles))
+}
+
+pub fn load<T, P>(path: P) -> Result<MeshImport<T>, StlError>
+where
+    T: cgmath::BaseFloat,
+    P: AsRef<Path>,
+{
+    parse(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a square split in two and a triangle with all its corners on one line
+    const TRIANGLES: [[[f32; 3]; 3]; 3] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]],
+    ];
+
+    fn check(import: MeshImport<f64>) {
+        // corners shared by the two halves of the square are welded
+        assert_eq!(import.mesh.positions.len(), 5);
+        assert_eq!(import.mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(import.degenerate, vec![2]);
+    }
+
+    #[test]
+    fn reads_ascii() {
+        let mut text = "solid square\n".to_string();
+        for triangle in TRIANGLES.iter() {
+            text += "facet normal 0 0 1\nouter loop\n";
+            for [x, y, z] in triangle.iter() {
+                text += &format!("vertex {} {} {}\n", x, y, z);
+            }
+            text += "endloop\nendfacet\n";
+        }
+        text += "endsolid square\n";
+        check(parse(text.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn reads_binary() {
+        // the header may start with "solid" too
+        let mut bytes = b"solid but actually binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend((TRIANGLES.len() as u32).to_le_bytes().iter());
+        for triangle in TRIANGLES.iter() {
+            for c in [0.0f32, 0.0, 1.0].iter().chain(triangle.iter().flatten()) {
+                bytes.extend(c.to_le_bytes().iter());
+            }
+            bytes.extend([0u8, 0].iter());
+        }
+        check(parse(&bytes).unwrap());
+
+        bytes.pop();
+        match parse::<f64>(&bytes) {
+            Err(StlError::Parse { .. }) | Err(StlError::Truncated { .. }) => (),
+            _ => panic!("expected a truncated file to be rejected"),
+        }
+    }
+
+    #[test]
+    fn facet_loops_need_three_vertices() {
+        let text = "solid s\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                    endloop\nendfacet\nendsolid s\n";
+        match parse::<f64>(text.as_bytes()) {
+            Err(StlError::Parse { line: 6, .. }) => (),
+            _ => panic!("expected a parse error on line 6"),
+        }
+    }
+}
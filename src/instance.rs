@@ -0,0 +1,168 @@
+use super::{Aabb, HitRecord, HitTable, Ray};
+use cgmath::{InnerSpace, Matrix, Matrix4, SquareMatrix, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+// places shared geometry in the world, the object is only ever seen in its own space
+pub struct Instance<T> {
+    object: Rc<dyn HitTable<T>>,
+    transform: Matrix4<T>,
+    inverse: Matrix4<T>,
+    // the inverse transpose, which keeps normals perpendicular under non-uniform scale
+    normal_matrix: Matrix4<T>,
+}
+
+impl<T: cgmath::BaseFloat> Instance<T> {
+    // None if the transform can't be inverted, e.g. a scale of zero along some axis
+    pub fn new(object: Rc<dyn HitTable<T>>, transform: Matrix4<T>) -> Option<Self> {
+        let inverse = transform.invert()?;
+        Some(Self {
+            object,
+            transform,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        })
+    }
+
+    pub fn transform(&self) -> &Matrix4<T> {
+        &self.transform
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Instance<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        // the direction isn't renormalized so t is the same in both spaces
+        let local = Ray::with_time(
+            (self.inverse * r.origin().extend(T::one())).truncate(),
+            (self.inverse * r.direction().extend(T::zero())).truncate(),
+            r.time(),
+        );
+        let mut rec = self.object.hit(&local, t)?;
+        rec.set_p((self.transform * rec.get_p().extend(T::one())).truncate());
+        let normal = (self.normal_matrix * rec.get_normal().extend(T::zero())).truncate();
+        rec.set_normal(normal.normalize());
+        let tangent = (self.transform * rec.get_tangent().extend(T::zero())).truncate();
+        let bitangent = (self.transform * rec.get_bitangent().extend(T::zero())).truncate();
+        rec.set_tangents(tangent, bitangent);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let bounds = self.object.bounding_box()?;
+        let (min, max) = (bounds.min(), bounds.max());
+        Aabb::from_points((0..8).map(|corner| {
+            let p = Vector3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+            (self.transform * p.extend(T::one())).truncate()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BoxShape, Lambertian, Material, Sphere};
+    use cgmath::vec3;
+    use rand::prelude::*;
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    fn close(a: Vector3<f64>, b: Vector3<f64>) -> bool {
+        (a - b).magnitude() < 1e-9
+    }
+
+    #[test]
+    fn scaled_and_moved_boxes_match_boxes_built_in_place() {
+        let unit = Rc::new(BoxShape::new(
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 1.0),
+            material(),
+        ));
+        let transform = Matrix4::from_translation(vec3(1.0, -1.0, 2.0))
+            * Matrix4::from_nonuniform_scale(2.0, 3.0, 0.5);
+        let instance = Instance::new(unit, transform).unwrap();
+        let world = BoxShape::new(vec3(1.0, -1.0, 2.0), vec3(3.0, 2.0, 2.5), material());
+
+        let bounds = instance.bounding_box().unwrap();
+        assert!(close(*bounds.min(), vec3(1.0, -1.0, 2.0)));
+        assert!(close(*bounds.max(), vec3(3.0, 2.0, 2.5)));
+
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = vec3(
+                rng.gen_range(-3.0, 7.0),
+                rng.gen_range(-5.0, 6.0),
+                rng.gen_range(-2.0, 6.0),
+            );
+            let target = vec3(
+                rng.gen_range(1.0, 3.0),
+                rng.gen_range(-1.0, 2.0),
+                rng.gen_range(2.0, 2.5),
+            );
+            let r = Ray::new(origin, target - origin);
+            let expected = world.hit(&r, 0.001..f64::MAX);
+            let found = instance.hit(&r, 0.001..f64::MAX);
+            assert_eq!(expected.is_some(), found.is_some());
+            if let (Some(expected), Some(found)) = (expected, found) {
+                assert!((expected.get_t() - found.get_t()).abs() < 1e-9);
+                assert!(close(*expected.get_p(), *found.get_p()));
+                assert!(close(*expected.get_normal(), *found.get_normal()));
+                assert_eq!(expected.get_front_face(), found.get_front_face());
+                hits += 1;
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn squashed_spheres_have_ellipsoid_normals() {
+        let unit = Rc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material()));
+        let (a, b, c) = (3.0, 1.0, 0.5);
+        let instance = Instance::new(unit, Matrix4::from_nonuniform_scale(a, b, c)).unwrap();
+        // aimed at (1, 0.5, z) on x^2/a^2 + y^2/b^2 + z^2/c^2 = 1, from straight above
+        let z = c * (1.0 - 1.0 / (a * a) - 0.25 / (b * b)).sqrt();
+        let p = vec3(1.0, 0.5, z);
+        let r = Ray::new(p + vec3(0.0, 0.0, 5.0), vec3(0.0, 0.0, -1.0));
+        let rec = instance.hit(&r, 0.0..f64::MAX).unwrap();
+        assert!(close(*rec.get_p(), p));
+        let gradient = vec3(p.x / (a * a), p.y / (b * b), p.z / (c * c)).normalize();
+        assert!(close(*rec.get_normal(), gradient));
+    }
+
+    #[test]
+    fn instances_share_one_object() {
+        let shared: Rc<dyn HitTable<f64>> =
+            Rc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material()));
+        let left = Instance::new(
+            Rc::clone(&shared),
+            Matrix4::from_translation(vec3(-3.0, 0.0, 0.0)),
+        )
+        .unwrap();
+        let right = Instance::new(
+            Rc::clone(&shared),
+            Matrix4::from_translation(vec3(3.0, 0.0, 0.0)) * Matrix4::from_scale(2.0),
+        )
+        .unwrap();
+        let down = |x: f64| Ray::new(vec3(x, 10.0, 0.0), vec3(0.0, -1.0, 0.0));
+
+        let rec = left.hit(&down(-3.0), 0.0..f64::MAX).unwrap();
+        assert!((rec.get_t() - 9.0).abs() < 1e-9);
+        assert!(right.hit(&down(-3.0), 0.0..f64::MAX).is_none());
+        let rec = right.hit(&down(3.0), 0.0..f64::MAX).unwrap();
+        assert!((rec.get_t() - 8.0).abs() < 1e-9);
+        assert!(close(*rec.get_normal(), vec3(0.0, 1.0, 0.0)));
+        assert!(left.hit(&down(3.0), 0.0..f64::MAX).is_none());
+    }
+
+    #[test]
+    fn singular_transforms_are_rejected() {
+        let sphere = Rc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, material()));
+        assert!(Instance::new(sphere, Matrix4::from_nonuniform_scale(1.0, 0.0, 1.0)).is_none());
+    }
+}
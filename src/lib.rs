@@ -1,14 +1,67 @@
+pub mod aabb;
+pub mod bvh;
 pub mod camera;
+pub mod cone;
+pub mod constant_medium;
+pub mod csg;
+pub mod cylinder;
+pub mod environment;
+pub mod grid_volume;
+pub mod heightfield;
 pub mod hit_table;
+pub mod import;
+pub mod instance;
+pub mod light;
 pub mod material;
+pub mod mesh;
+pub mod moving_sphere;
+pub mod onb;
+pub mod perlin;
 pub mod pixel;
+pub mod plane;
+pub mod procedural;
 pub mod ray;
+pub mod rect;
+pub mod sdf;
+pub mod sky;
 pub mod sphere;
 pub mod support;
+pub mod texture;
+pub mod torus;
+pub mod triangle;
 
+pub use aabb::Aabb;
+pub use bvh::Bvh;
 pub use camera::Camera;
-pub use hit_table::{HitRecord, HitTable, HitTableList};
-pub use material::{Dielectric, Lambertian, Material, Metal};
+pub use cone::Cone;
+pub use constant_medium::ConstantMedium;
+pub use csg::{Csg, CsgOp, Solid, Span};
+pub use cylinder::Cylinder;
+pub use environment::{ConstantEnvironment, Environment, GradientEnvironment, HdrEnvironment};
+pub use grid_volume::{DensityGrid, GridVolume};
+pub use heightfield::{HeightMap, Heightfield};
+pub use hit_table::{HitRecord, HitTable, HitTableList, OneSided};
+pub use instance::Instance;
+pub use light::{
+    AreaLight, DeltaLight, DirectionalLight, LightList, MisHeuristic, PointLight, SpotLight,
+};
+pub use material::{
+    Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, ScatterRecord,
+};
+pub use mesh::{MeshData, MeshError, TriangleMesh};
+pub use moving_sphere::MovingSphere;
+pub use onb::Onb;
+pub use perlin::Perlin;
 pub use pixel::Pixel;
+pub use plane::{Disk, Plane};
+pub use procedural::{ColorRamp, Granite, Marble, Wood};
 pub use ray::Ray;
+pub use rect::{BoxShape, FlipFace, XyRect, XzRect, YzRect};
+pub use sdf::{
+    Repeat, Sdf, SdfBox, SdfObject, SdfRoundBox, SdfSphere, SdfTorus, SmoothUnion, Twist,
+};
+pub use sky::PreethamSky;
 pub use sphere::Sphere;
+pub use texture::{Checker, ImageTexture, SolidColor, Texture, WrapMode};
+pub use torus::Torus;
+pub use triangle::Triangle;
@@ -0,0 +1,349 @@
+use cgmath::{InnerSpace, Vector3};
+use rand::distributions::Standard;
+use rand::prelude::*;
+use std::rc::Rc;
+
+// geometry that direct lighting can aim rays at. directions are sampled by solid angle as seen
+// from `origin`, and `pdf` is the density of `sample` producing `direction` (zero if that
+// direction misses the light)
+pub trait AreaLight<T> {
+    fn sample(&self, origin: &Vector3<T>) -> Vector3<T>;
+    fn pdf(&self, origin: &Vector3<T>, direction: &Vector3<T>) -> T;
+}
+
+// the scene's registered lights, sampled as an equal mixture. the shapes only describe where
+// to aim, what they emit comes from whatever the shadow ray hits in the world, or from the
+// environment if it hits nothing
+pub struct LightList<T> {
+    lights: Vec<Rc<dyn AreaLight<T>>>,
+}
+
+impl<T> LightList<T> {
+    pub fn new() -> Self {
+        Self { lights: vec![] }
+    }
+
+    pub fn add(&mut self, light: Rc<dyn AreaLight<T>>) {
+        self.lights.push(light)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+}
+
+impl<T> Default for LightList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> AreaLight<T> for LightList<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    fn sample(&self, origin: &Vector3<T>) -> Vector3<T> {
+        let i = thread_rng().gen_range(0, self.lights.len());
+        self.lights[i].sample(origin)
+    }
+
+    // lights can overlap as seen from `origin`, so every one that could have produced the
+    // direction counts
+    fn pdf(&self, origin: &Vector3<T>, direction: &Vector3<T>) -> T {
+        let sum = self
+            .lights
+            .iter()
+            .fold(T::zero(), |acc, light| acc + light.pdf(origin, direction));
+        sum / T::from(self.lights.len()).unwrap()
+    }
+}
+
+// how a light sample and a bsdf sample that could both have found the same light share it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MisHeuristic {
+    Balance,
+    Power,
+}
+
+impl MisHeuristic {
+    // the weight of a sample drawn with density `pdf` that the other strategy draws with
+    // density `other`. the two weights of any direction either strategy can draw sum to one
+    pub fn weight<T: cgmath::BaseFloat>(self, pdf: T, other: T) -> T {
+        let (a, b) = match self {
+            MisHeuristic::Balance => (pdf, other),
+            MisHeuristic::Power => (pdf * pdf, other * other),
+        };
+        if a + b > T::zero() {
+            a / (a + b)
+        } else {
+            T::zero()
+        }
+    }
+}
+
+// a light with no size, which bounces can never find by chance. `illuminate` gives the unit
+// direction from `p` towards the light, the distance to it and the irradiance it delivers to a
+// surface facing it, or None if it doesn't reach `p` at all
+pub trait DeltaLight<T> {
+    fn illuminate(&self, p: &Vector3<T>) -> Option<(Vector3<T>, T, Vector3<T>)>;
+}
+
+// `intensity` over distance to the power of `falloff`, where 2 is physical and anything lower
+// carries further
+fn attenuate<T: cgmath::BaseFloat>(
+    position: &Vector3<T>,
+    p: &Vector3<T>,
+    intensity: Vector3<T>,
+    falloff: T,
+) -> Option<(Vector3<T>, T, Vector3<T>)> {
+    let to_light = position - p;
+    let distance = to_light.magnitude();
+    if distance <= T::zero() {
+        return None;
+    }
+    Some((
+        to_light / distance,
+        distance,
+        intensity / distance.powf(falloff),
+    ))
+}
+
+pub struct PointLight<T> {
+    position: Vector3<T>,
+    intensity: Vector3<T>,
+    falloff: T,
+}
+
+impl<T: cgmath::BaseFloat> PointLight<T> {
+    pub fn new(position: Vector3<T>, intensity: Vector3<T>) -> Self {
+        Self {
+            position,
+            intensity,
+            falloff: T::from(2.0).unwrap(),
+        }
+    }
+
+    pub fn set_falloff(&mut self, falloff: T) {
+        self.falloff = falloff;
+    }
+}
+
+impl<T: cgmath::BaseFloat> DeltaLight<T> for PointLight<T> {
+    fn illuminate(&self, p: &Vector3<T>) -> Option<(Vector3<T>, T, Vector3<T>)> {
+        attenuate(&self.position, p, self.intensity, self.falloff)
+    }
+}
+
+// a point light shining down a cone, at full strength inside `inner` degrees of its axis and
+// fading out smoothly by `outer`
+pub struct SpotLight<T> {
+    position: Vector3<T>,
+    direction: Vector3<T>,
+    intensity: Vector3<T>,
+    cos_inner: T,
+    cos_outer: T,
+    falloff: T,
+}
+
+impl<T: cgmath::BaseFloat> SpotLight<T> {
+    pub fn new(
+        position: Vector3<T>,
+        direction: Vector3<T>,
+        intensity: Vector3<T>,
+        inner: T,
+        outer: T,
+    ) -> Self {
+        let outer = outer.max(inner);
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            cos_inner: inner.to_radians().cos(),
+            cos_outer: outer.to_radians().cos(),
+            falloff: T::from(2.0).unwrap(),
+        }
+    }
+
+    pub fn set_falloff(&mut self, falloff: T) {
+        self.falloff = falloff;
+    }
+}
+
+impl<T: cgmath::BaseFloat> DeltaLight<T> for SpotLight<T> {
+    fn illuminate(&self, p: &Vector3<T>) -> Option<(Vector3<T>, T, Vector3<T>)> {
+        let (to_light, distance, irradiance) =
+            attenuate(&self.position, p, self.intensity, self.falloff)?;
+        let cosine = -to_light.dot(self.direction);
+        if cosine <= self.cos_outer {
+            return None;
+        }
+        let spread = self.cos_inner - self.cos_outer;
+        let t = if spread > T::zero() {
+            ((cosine - self.cos_outer) / spread).min(T::one())
+        } else {
+            T::one()
+        };
+        let smooth = t * t * (T::from(3.0).unwrap() - T::from(2.0).unwrap() * t);
+        Some((to_light, distance, irradiance * smooth))
+    }
+}
+
+// parallel light from infinitely far away, like the sun. `direction` is the way the light travels
+pub struct DirectionalLight<T> {
+    direction: Vector3<T>,
+    irradiance: Vector3<T>,
+}
+
+impl<T: cgmath::BaseFloat> DirectionalLight<T> {
+    pub fn new(direction: Vector3<T>, irradiance: Vector3<T>) -> Self {
+        Self {
+            direction: direction.normalize(),
+            irradiance,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> DeltaLight<T> for DirectionalLight<T> {
+    fn illuminate(&self, _p: &Vector3<T>) -> Option<(Vector3<T>, T, Vector3<T>)> {
+        Some((-self.direction, T::infinity(), self.irradiance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Lambertian, Sphere, XyRect, YzRect};
+    use cgmath::vec3;
+    use std::f64::consts::PI;
+
+    // the unit sphere of directions in equal area bins, by z and by the angle around z
+    const BINS: (usize, usize) = (8, 16);
+
+    fn bin(d: Vector3<f64>) -> usize {
+        let d = d.normalize();
+        let z = ((d.z + 1.0) * 0.5 * BINS.0 as f64) as usize;
+        let phi = (d.y.atan2(d.x) + PI) / (2.0 * PI) * BINS.1 as f64;
+        z.min(BINS.0 - 1) * BINS.1 + (phi as usize).min(BINS.1 - 1)
+    }
+
+    // integrates the pdf over the sphere of directions on a fine grid and checks it adds up to
+    // one, and that each bin gets the share of samples the pdf says it should
+    fn check_light(light: &dyn AreaLight<f64>, origin: Vector3<f64>) {
+        let (nz, nphi) = (BINS.0 * 32, BINS.1 * 32);
+        let cell = 4.0 * PI / (nz * nphi) as f64;
+        let mut expected = vec![0.0; BINS.0 * BINS.1];
+        for i in 0..nz {
+            for j in 0..nphi {
+                let z = (i as f64 + 0.5) / nz as f64 * 2.0 - 1.0;
+                let phi = (j as f64 + 0.5) / nphi as f64 * 2.0 * PI - PI;
+                let r = (1.0 - z * z).sqrt();
+                let d = vec3(r * phi.cos(), r * phi.sin(), z);
+                expected[bin(d)] += light.pdf(&origin, &d) * cell;
+            }
+        }
+        let total: f64 = expected.iter().sum();
+        assert!((total - 1.0).abs() < 0.01, "pdf integrates to {}", total);
+
+        let n = 100_000;
+        let mut found = vec![0.0; BINS.0 * BINS.1];
+        for _ in 0..n {
+            let d = light.sample(&origin);
+            assert!(light.pdf(&origin, &d) > 0.0);
+            found[bin(d)] += 1.0 / n as f64;
+        }
+        for (i, (e, f)) in expected.iter().zip(&found).enumerate() {
+            assert!(
+                (e - f).abs() < 0.01,
+                "bin {}: pdf {} but sampled {}",
+                i,
+                e,
+                f
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_pdfs_match_their_samples() {
+        let material = Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(vec3(0.5, -0.25, 1.0), 0.75, material);
+        check_light(&sphere, vec3(-1.0, 1.0, -2.0));
+        // close up the cone is wide
+        check_light(&sphere, vec3(0.5, -0.25, 1.0) + vec3(0.6, 0.6, 0.0));
+        // from inside every direction is equally likely
+        check_light(&sphere, vec3(0.6, -0.25, 1.2));
+    }
+
+    #[test]
+    fn rect_pdfs_match_their_samples() {
+        let material = || Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let xy = XyRect::new((-1.0, 2.0), (0.0, 1.0), 1.5, material());
+        check_light(&xy, vec3(0.0, 0.0, 0.0));
+        // from behind and off to one side
+        check_light(&xy, vec3(3.0, -1.0, 2.5));
+        let yz = YzRect::new((-0.5, 0.5), (-0.5, 0.5), -1.0, material());
+        check_light(&yz, vec3(0.0, 0.25, 0.0));
+    }
+
+    #[test]
+    fn mis_weights_of_a_direction_sum_to_one() {
+        let pdfs = [1e-6f64, 0.01, 0.3, 1.0, 2.5, 40.0, 1e6];
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power].iter() {
+            for a in pdfs.iter() {
+                for b in pdfs.iter() {
+                    let sum = heuristic.weight(*a, *b) + heuristic.weight(*b, *a);
+                    assert!((sum - 1.0).abs() < 1e-12, "{:?} {} {}", heuristic, a, b);
+                }
+                // a strategy that can't draw the direction leaves it all to the other
+                assert_eq!(heuristic.weight(*a, 0.0), 1.0);
+                assert_eq!(heuristic.weight(0.0, *a), 0.0);
+            }
+        }
+        assert!((MisHeuristic::Power.weight(3.0f64, 1.0) - 0.9).abs() < 1e-12);
+        assert!((MisHeuristic::Balance.weight(3.0f64, 1.0) - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_distance() {
+        let mut light = PointLight::new(vec3(0.0, 4.0, 0.0), vec3(16.0, 16.0, 16.0));
+        let (direction, distance, irradiance) = light.illuminate(&vec3(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(direction, vec3(0.0, 1.0, 0.0));
+        assert_eq!(distance, 4.0);
+        assert_eq!(irradiance, vec3(1.0, 1.0, 1.0));
+        light.set_falloff(1.0);
+        let (_, _, irradiance) = light.illuminate(&vec3(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(irradiance, vec3(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn spot_lights_fade_between_their_cones() {
+        let light = SpotLight::new(
+            vec3(0.0, 1.0, 0.0),
+            vec3(0.0, -1.0, 0.0),
+            vec3(1.0, 1.0, 1.0),
+            20.0,
+            40.0,
+        );
+        let at = |degrees: f64| {
+            let p = vec3(degrees.to_radians().tan(), 0.0, 0.0);
+            let distance2 = p.x * p.x + 1.0;
+            light
+                .illuminate(&p)
+                .map_or(0.0, |(_, _, irradiance)| irradiance.x * distance2)
+        };
+        assert!((at(0.0) - 1.0).abs() < 1e-12);
+        assert!((at(19.0) - 1.0).abs() < 1e-12);
+        let (a, b) = (at(25.0), at(35.0));
+        assert!(a < 1.0 && b < a && b > 0.0);
+        assert_eq!(at(41.0), 0.0);
+    }
+
+    #[test]
+    fn directional_lights_reach_everywhere() {
+        let light = DirectionalLight::new(vec3(0.0, -2.0, 0.0), vec3(3.0f64, 3.0, 3.0));
+        let (direction, distance, irradiance) = light.illuminate(&vec3(5.0, -7.0, 1.0)).unwrap();
+        assert_eq!(direction, vec3(0.0, 1.0, 0.0));
+        assert!(distance.is_infinite());
+        assert_eq!(irradiance, vec3(3.0, 3.0, 3.0));
+    }
+}
@@ -1,6 +1,6 @@
 use raytracer_in_a_weekend::*;
 
-use cgmath::{vec3, ElementWise, InnerSpace, Vector3};
+use cgmath::{vec3, Deg, ElementWise, Matrix4, Vector3};
 use glutin::event::{Event, VirtualKeyCode, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::window::WindowBuilder;
@@ -10,26 +10,49 @@ use std::rc::Rc;
 
 struct App {
     pixels: Vec<Pixel>,
-    world: HitTableList<f64>,
+    world: Bvh<f64>,
     camera: Camera<f64>,
+    environment: Rc<dyn Environment<f64>>,
+    // emitters registered here are sampled directly, the rest are only found by bounces
+    lights: LightList<f64>,
+    // point, spot and directional lights, which only shadow rays can reach
+    delta_lights: Vec<Box<dyn DeltaLight<f64>>>,
+    heuristic: MisHeuristic,
     rng: ThreadRng,
 }
 
 impl App {
-    fn color(&self, r: &Ray<f64>, depth: usize) -> Vector3<f64> {
+    // `emitted_weight` is the share of any light hit here that this path accounts for, the
+    // rest having been found by sampling the lights at the previous bounce
+    fn color(&self, r: &Ray<f64>, depth: usize, emitted_weight: f64) -> Vector3<f64> {
         if depth < 50 {
             match self.world.hit(r, 0.001..std::f64::MAX) {
-                None => {
-                    let unit_direction = r.direction().normalize();
-                    let t = 0.5 * (unit_direction.y + 1.0);
-                    (1.0 - t) * vec3(1.0, 1.0, 1.0) + t * vec3(0.5, 0.7, 1.0)
-                }
+                None => self.environment.radiance(r.direction()) * emitted_weight,
                 Some(hit) => {
-                    if let Some((attenuation, ray)) = hit.get_material().scatter(r, &hit) {
-                        attenuation.mul_element_wise(self.color(&ray, depth + 1))
-                    } else {
+                    let emitted = hit.get_material().emitted(r, &hit) * emitted_weight;
+                    // light sampling doesn't depend on the bsdf sample, so it still counts when
+                    // that sample is absorbed. both are black for materials without an `eval`
+                    let direct = if self.lights.is_empty() {
                         vec3(0.0, 0.0, 0.0)
-                    }
+                    } else {
+                        self.direct_light(r, &hit)
+                    };
+                    let punctual = self.delta_light(r, &hit);
+                    let scatter = match hit.get_material().scatter(r, &hit) {
+                        Some(scatter) => scatter,
+                        None => return emitted + direct + punctual,
+                    };
+                    // specular bounces can't be lit by light sampling, so they keep all of it
+                    let weight = match scatter.pdf {
+                        Some(pdf) if !self.lights.is_empty() => {
+                            let direction = scatter.ray.direction();
+                            let light_pdf = self.lights.pdf(hit.get_p(), direction);
+                            self.heuristic.weight(pdf, light_pdf)
+                        }
+                        _ => 1.0,
+                    };
+                    let indirect = self.color(&scatter.ray, depth + 1, weight);
+                    emitted + direct + punctual + scatter.attenuation.mul_element_wise(indirect)
                 }
             }
         } else {
@@ -37,6 +60,50 @@ impl App {
         }
     }
 
+    // every delta light that reaches the hit unoccluded. like sampling area lights, this only
+    // works for materials that can be evaluated in any direction
+    fn delta_light(&self, r: &Ray<f64>, hit: &HitRecord<f64>) -> Vector3<f64> {
+        let black = vec3(0.0, 0.0, 0.0);
+        let origin = *hit.get_p();
+        self.delta_lights.iter().fold(black, |acc, light| {
+            let (direction, distance, irradiance) = match light.illuminate(&origin) {
+                Some(incoming) => incoming,
+                None => return acc,
+            };
+            let f = match hit.get_material().eval(r, hit, &direction) {
+                Some(f) if f != black => f,
+                _ => return acc,
+            };
+            let shadow = Ray::with_time(origin, direction, r.time());
+            let end = (distance - 0.001).min(std::f64::MAX);
+            if self.world.hit(&shadow, 0.001..end).is_some() {
+                return acc;
+            }
+            acc + f.mul_element_wise(irradiance)
+        })
+    }
+
+    // light arriving straight from one of the registered lights, weighted against the chance of
+    // the bsdf finding the same light
+    fn direct_light(&self, r: &Ray<f64>, hit: &HitRecord<f64>) -> Vector3<f64> {
+        let black = vec3(0.0, 0.0, 0.0);
+        let origin = *hit.get_p();
+        let direction = self.lights.sample(&origin);
+        let light_pdf = self.lights.pdf(&origin, &direction);
+        let f = match hit.get_material().eval(r, hit, &direction) {
+            Some(f) if light_pdf > 0.0 && f != black => f,
+            _ => return black,
+        };
+        let shadow = Ray::with_time(origin, direction, r.time());
+        let emitted = match self.world.hit(&shadow, 0.001..std::f64::MAX) {
+            Some(light) => light.get_material().emitted(&shadow, &light),
+            None => self.environment.radiance(&direction),
+        };
+        let bsdf_pdf = hit.get_material().pdf(r, hit, &direction);
+        let weight = self.heuristic.weight(light_pdf, bsdf_pdf);
+        f.mul_element_wise(emitted) * (weight / light_pdf)
+    }
+
     fn draw(&mut self, width: usize, height: usize) {
         const AA_STEPS: usize = 100;
         let mut i = 0usize;
@@ -47,7 +114,7 @@ impl App {
                     let v = (y as f64 + self.rng.gen::<f64>()) / (height as f64);
 
                     let r = self.camera.ray(u, v);
-                    acc + self.color(&r, 0)
+                    acc + self.color(&r, 0, 1.0)
                 }) / AA_STEPS as f64;
 
                 self.pixels[i].r = (col.x.sqrt() * 255.99) as u8;
@@ -125,6 +192,135 @@ fn gen_world(rng: &mut ThreadRng) -> HitTableList<f64> {
     list
 }
 
+// the classic box lit only by a lamp in its ceiling, meant for a black background
+fn cornell_box() -> (HitTableList<f64>, LightList<f64>) {
+    let red: Rc<dyn Material<f64>> = Rc::new(Lambertian::new(vec3(0.65, 0.05, 0.05)));
+    let white: Rc<dyn Material<f64>> = Rc::new(Lambertian::new(vec3(0.73, 0.73, 0.73)));
+    let green: Rc<dyn Material<f64>> = Rc::new(Lambertian::new(vec3(0.12, 0.45, 0.15)));
+    let light: Rc<dyn Material<f64>> = Rc::new(DiffuseLight::new(vec3(15.0, 15.0, 15.0)));
+
+    let mut list = HitTableList::new();
+    list.add(Box::new(YzRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        555.0,
+        green,
+    )));
+    list.add(Box::new(YzRect::new((0.0, 555.0), (0.0, 555.0), 0.0, red)));
+    // the lamp faces down into the room
+    let lamp = || XzRect::new((213.0, 343.0), (227.0, 332.0), 554.0, Rc::clone(&light));
+    list.add(Box::new(FlipFace::new(Box::new(lamp()))));
+    let mut lights = LightList::new();
+    lights.add(Rc::new(lamp()));
+    list.add(Box::new(XzRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        0.0,
+        Rc::clone(&white),
+    )));
+    list.add(Box::new(XzRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        555.0,
+        Rc::clone(&white),
+    )));
+    list.add(Box::new(XyRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        555.0,
+        Rc::clone(&white),
+    )));
+
+    let block = |size: Vector3<f64>, angle: f64, offset: Vector3<f64>| {
+        let shape = BoxShape::new(vec3(0.0, 0.0, 0.0), size, Rc::clone(&white));
+        let transform = Matrix4::from_translation(offset) * Matrix4::from_angle_y(Deg(angle));
+        Box::new(Instance::new(Rc::new(shape), transform).unwrap())
+    };
+    list.add(block(
+        vec3(165.0, 330.0, 165.0),
+        15.0,
+        vec3(265.0, 0.0, 295.0),
+    ));
+    list.add(block(
+        vec3(165.0, 165.0, 165.0),
+        -18.0,
+        vec3(130.0, 0.0, 65.0),
+    ));
+    (list, lights)
+}
+
+fn cornell_camera(aspect: f64) -> Camera<f64> {
+    let origin = vec3(278.0, 278.0, -800.0);
+    let look_at = vec3(278.0, 278.0, 0.0);
+    Camera::new(origin, look_at, Vector3::unit_y(), 40.0, aspect, 0.0, 10.0)
+}
+
+// a few materials on a floor, lit like a studio by the lights below
+fn lookdev() -> HitTableList<f64> {
+    let mut list = HitTableList::new();
+    list.add(Box::new(XzRect::new(
+        (-20.0, 20.0),
+        (-20.0, 20.0),
+        0.0,
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))),
+    )));
+    list.add(Box::new(Sphere::new(
+        vec3(0.0, 1.0, -2.2),
+        1.0,
+        Rc::new(Lambertian::new(vec3(0.7, 0.1, 0.1))),
+    )));
+    list.add(Box::new(Sphere::new(
+        vec3(0.0, 1.0, 0.0),
+        1.0,
+        Rc::new(Metal::new(vec3(0.8, 0.8, 0.8), 0.2)),
+    )));
+    list.add(Box::new(Sphere::new(
+        vec3(0.0, 1.0, 2.2),
+        1.0,
+        Rc::new(Dielectric::new(1.5)),
+    )));
+    list
+}
+
+fn lookdev_lights() -> Vec<Box<dyn DeltaLight<f64>>> {
+    let key = SpotLight::new(
+        vec3(6.0, 8.0, 4.0),
+        vec3(-6.0, -7.0, -4.0),
+        vec3(150.0, 140.0, 120.0),
+        20.0,
+        30.0,
+    );
+    let fill = PointLight::new(vec3(8.0, 3.0, -6.0), vec3(15.0, 17.0, 20.0));
+    let rim = DirectionalLight::new(vec3(1.0, -0.6, 0.3), vec3(0.6, 0.6, 0.7));
+    vec![Box::new(key), Box::new(fill), Box::new(rim)]
+}
+
+fn default_camera(aspect: f64) -> Camera<f64> {
+    let origin = vec3(13.0, 2.0, 3.0);
+    let look_at = vec3(0.0, 0.0, 0.0);
+    Camera::new(origin, look_at, Vector3::unit_y(), 20.0, aspect, 0.1, 10.0)
+}
+
+// removes `name` and the value after it from the arguments
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == name)?;
+    let value = args
+        .get(i + 1)
+        .cloned()
+        .unwrap_or_else(|| panic!("{} needs a value", name));
+    args.drain(i..i + 2);
+    Some(value)
+}
+
+// removes `name` from the arguments, saying whether it was there
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let i = args.iter().position(|arg| arg == name);
+    if let Some(i) = i {
+        args.remove(i);
+    }
+    i.is_some()
+}
+
 fn main() {
     const WIDTH: usize = 400;
     const HEIGHT: usize = 200;
@@ -153,29 +349,92 @@ fn main() {
 
     let mut app = {
         let mut rng = rand::thread_rng();
-        let world = gen_world(&mut rng);
 
         let mut pixels: Vec<Pixel> = Vec::new();
         pixels.resize(WIDTH * HEIGHT, Pixel::default());
 
-        let camera = {
-            let origin = vec3(13.0, 2.0, 3.0);
-            let look_at = vec3(0.0, 0.0, 0.0);
-            Camera::new(
-                origin,
-                look_at,
-                Vector3::unit_y(),
-                20.0,
-                WIDTH as f64 / HEIGHT as f64,
-                0.1,
-                10.0,
-            )
+        let aspect = WIDTH as f64 / HEIGHT as f64;
+        let mut args: Vec<String> = std::env::args().skip(1).collect();
+        // `--hdr <file>` lights the scene with an environment map and `--sky <hours>` with
+        // daylight at that time, instead of the gradient
+        let hdr = take_option(&mut args, "--hdr");
+        let hours = take_option(&mut args, "--sky");
+        if hdr.is_some() && hours.is_some() {
+            panic!("--hdr and --sky both replace the environment, pick one");
+        }
+        // `--balance` weighs light and bsdf samples with the balance heuristic instead of the
+        // power heuristic
+        let heuristic = if take_flag(&mut args, "--balance") {
+            MisHeuristic::Balance
+        } else {
+            MisHeuristic::Power
+        };
+        let gradient = || -> Rc<dyn Environment<f64>> {
+            Rc::new(GradientEnvironment::new(
+                vec3(1.0, 1.0, 1.0),
+                vec3(0.5, 0.7, 1.0),
+            ))
         };
+        let mut delta_lights = vec![];
+        let (world, camera, mut environment, mut lights) = match args.first() {
+            Some(arg) if arg == "--lookdev" => {
+                delta_lights = lookdev_lights();
+                let dim: Rc<dyn Environment<f64>> =
+                    Rc::new(ConstantEnvironment::new(vec3(0.02, 0.02, 0.03)));
+                (
+                    Bvh::from(lookdev()),
+                    default_camera(aspect),
+                    dim,
+                    LightList::new(),
+                )
+            }
+            Some(arg) if arg == "--cornell" => {
+                let (world, lights) = cornell_box();
+                let black: Rc<dyn Environment<f64>> =
+                    Rc::new(ConstantEnvironment::new(vec3(0.0, 0.0, 0.0)));
+                (Bvh::from(world), cornell_camera(aspect), black, lights)
+            }
+            Some(path) => {
+                let scene = import::gltf::load(path, Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))))
+                    .unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+                let camera = match scene.camera {
+                    Some(camera) => camera.to_camera(aspect),
+                    None => default_camera(aspect),
+                };
+                (Bvh::from(scene.world), camera, gradient(), LightList::new())
+            }
+            None => (
+                Bvh::from(gen_world(&mut rng)),
+                default_camera(aspect),
+                gradient(),
+                LightList::new(),
+            ),
+        };
+        if let Some(path) = hdr {
+            let map = HdrEnvironment::load(&path)
+                .unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+            let map = Rc::new(map);
+            lights.add(Rc::clone(&map) as Rc<dyn AreaLight<f64>>);
+            environment = map;
+        }
+        if let Some(hours) = hours {
+            let hours = hours.parse().expect("--sky needs a time in hours");
+            let mut sky = PreethamSky::new(Vector3::unit_y(), 3.0, vec3(0.3, 0.3, 0.3));
+            // a midsummer day at a mid northern latitude
+            sky.set_time_of_day(hours, 40.0, 172);
+            let sky = Rc::new(sky);
+            lights.add(Rc::clone(&sky) as Rc<dyn AreaLight<f64>>);
+            environment = sky;
+        }
 
         App {
             pixels,
             world,
             camera,
+            environment,
+            lights,
+            delta_lights,
+            heuristic,
             rng,
         }
     };
@@ -210,3 +469,149 @@ fn main() {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    fn app(world: HitTableList<f64>, lights: LightList<f64>) -> App {
+        App {
+            pixels: vec![],
+            world: Bvh::from(world),
+            camera: cornell_camera(1.0),
+            environment: Rc::new(ConstantEnvironment::new(vec3(0.0, 0.0, 0.0))),
+            lights,
+            delta_lights: vec![],
+            heuristic: MisHeuristic::Power,
+            rng: rand::thread_rng(),
+        }
+    }
+
+    // the mean of `n` paths along `r`
+    fn mean(app: &App, r: &Ray<f64>, depth: usize, n: usize) -> Vector3<f64> {
+        (0..n).fold(vec3(0.0, 0.0, 0.0), |acc, _| acc + app.color(r, depth, 1.0)) / n as f64
+    }
+
+    #[test]
+    fn emission_is_carried_along_bounces() {
+        // a mirror floor under a lamp facing down
+        let mut world = HitTableList::new();
+        world.add(Box::new(XzRect::new(
+            (-5.0, 5.0),
+            (-5.0, 5.0),
+            0.0,
+            Rc::new(Metal::new(vec3(0.8, 0.5, 0.25), 0.0)),
+        )));
+        world.add(Box::new(FlipFace::new(Box::new(XzRect::new(
+            (-5.0, 5.0),
+            (-5.0, 5.0),
+            2.0,
+            Rc::new(DiffuseLight::new(vec3(4.0, 4.0, 4.0))),
+        )))));
+        let app = app(world, LightList::new());
+
+        let up = Ray::new(vec3(0.0, 1.0, 0.0), vec3(0.0, 1.0, 0.0));
+        assert_eq!(app.color(&up, 0, 1.0), vec3(4.0, 4.0, 4.0));
+        // only the share of the light this path is responsible for
+        assert_eq!(app.color(&up, 0, 0.25), vec3(1.0, 1.0, 1.0));
+        let bounced = Ray::new(vec3(-1.0, 1.0, 0.0), vec3(1.0, -1.0, 0.0));
+        assert_eq!(app.color(&bounced, 0, 1.0), vec3(3.2, 2.0, 1.0));
+        // paths that run out of bounces are dark
+        assert_eq!(app.color(&bounced, 50, 1.0), vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cornell_lamp_shines_down_into_the_room() {
+        let (world, lights) = cornell_box();
+        let app = app(world, lights);
+        let up = Ray::new(vec3(278.0, 1.0, 278.0), vec3(0.0, 1.0, 0.0));
+        assert_eq!(app.color(&up, 0, 1.0), vec3(15.0, 15.0, 15.0));
+        // between the lamp and the ceiling, looking at its back
+        let down = Ray::new(vec3(278.0, 554.5, 278.0), vec3(0.0, -1.0, 0.0));
+        assert_eq!(app.color(&down, 0, 1.0), vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cornell_light_sampling_agrees_with_bsdf_sampling() {
+        // the floor under the lamp. sampling the lamp directly changes the noise but not the
+        // mean, as long as the paths are deep enough that the last bounce, which only light
+        // sampling can light, is too dim to matter
+        let r = Ray::new(vec3(278.0, 10.0, 278.0), vec3(0.0, -1.0, 0.0));
+        let (world, lights) = cornell_box();
+        let sampled = mean(&app(world, lights), &r, 38, 3000);
+        let (world, _) = cornell_box();
+        let bounced = mean(&app(world, LightList::new()), &r, 38, 30000);
+        assert!((sampled - bounced).magnitude() < 0.15 * sampled.magnitude());
+    }
+
+    #[test]
+    fn fuzzy_metal_is_lit_by_light_sampling() {
+        // a rough mirror under a square lamp, with nothing else to light it
+        let mut world = HitTableList::new();
+        world.add(Box::new(XzRect::new(
+            (-10.0, 10.0),
+            (-10.0, 10.0),
+            0.0,
+            Rc::new(Metal::new(vec3(0.9, 0.9, 0.9), 0.8)),
+        )));
+        let lamp = || {
+            XzRect::new(
+                (-1.0, 1.0),
+                (-1.0, 1.0),
+                1.0,
+                Rc::new(DiffuseLight::new(vec3(5.0, 5.0, 5.0))),
+            )
+        };
+        world.add(Box::new(FlipFace::new(Box::new(lamp()))));
+        let mut lights = LightList::new();
+        lights.add(Rc::new(lamp()));
+        let app = app(world, lights);
+
+        // the light reflected off the metal, integrated over the lamp's area
+        let r = Ray::new(vec3(-1.5, 0.9, 0.0), vec3(1.5, -0.9, 0.0));
+        let hit = app.world.hit(&r, 0.001..std::f64::MAX).unwrap();
+        let n = 200;
+        let step = 2.0 / n as f64;
+        let mut reference = vec3(0.0, 0.0, 0.0);
+        for i in 0..n {
+            for j in 0..n {
+                let x = -1.0 + (i as f64 + 0.5) * step;
+                let z = -1.0 + (j as f64 + 0.5) * step;
+                let to_lamp = vec3(x, 1.0, z) - hit.get_p();
+                let direction = to_lamp.normalize();
+                let f = hit.get_material().eval(&r, &hit, &direction).unwrap();
+                let solid_angle = direction.y * step * step / to_lamp.magnitude2();
+                reference += f * 5.0 * solid_angle;
+            }
+        }
+        let estimate = mean(&app, &r, 0, 20000);
+        assert!((estimate - reference).magnitude() < 0.05 * reference.magnitude());
+    }
+
+    #[test]
+    fn fuzzy_metal_is_lit_by_point_lights() {
+        let mut world = HitTableList::new();
+        world.add(Box::new(XzRect::new(
+            (-10.0, 10.0),
+            (-10.0, 10.0),
+            0.0,
+            Rc::new(Metal::new(vec3(0.9, 0.9, 0.9), 0.8)),
+        )));
+        let mut app = app(world, LightList::new());
+        app.delta_lights.push(Box::new(PointLight::new(
+            vec3(0.5, 1.0, 0.0),
+            vec3(5.0, 5.0, 5.0),
+        )));
+
+        // nothing else lights the metal and the light can't be hit, so every path sees the same
+        // light, whether its bounce was absorbed or not
+        let r = Ray::new(vec3(-1.5, 0.9, 0.0), vec3(1.5, -0.9, 0.0));
+        let hit = app.world.hit(&r, 0.001..std::f64::MAX).unwrap();
+        let punctual = app.delta_light(&r, &hit);
+        assert!(punctual.x > 0.0);
+        for _ in 0..1000 {
+            assert!((app.color(&r, 0, 1.0) - punctual).magnitude() < 1e-12);
+        }
+    }
+}
@@ -1,7 +1,8 @@
-use super::{HitRecord, Ray};
+use super::{HitRecord, Ray, SolidColor, Texture};
 use cgmath::{vec3, InnerSpace, Vector3};
 use rand::distributions::Standard;
 use rand::prelude::*;
+use std::rc::Rc;
 
 fn rand_in_unit_sphere<T>() -> Vector3<T>
 where
@@ -21,16 +22,78 @@ where
     }
 }
 
+// a point on the unit sphere, uniformly distributed
+fn rand_unit_vector<T>() -> Vector3<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    loop {
+        let p = rand_in_unit_sphere::<T>();
+        let length2 = p.magnitude2();
+        if length2 > T::from(1e-8).unwrap() {
+            return p / length2.sqrt();
+        }
+    }
+}
+
+// one sampled bounce. `attenuation` is the bsdf times the cosine over `pdf`, the density the
+// direction was drawn with. specular bounces have no density and leave `pdf` as None
+pub struct ScatterRecord<T> {
+    pub attenuation: Vector3<T>,
+    pub ray: Ray<T>,
+    pub pdf: Option<T>,
+}
+
 pub trait Material<T> {
-    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vector3<T>, Ray<T>)>;
+    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<ScatterRecord<T>>;
+
+    // radiance given off at the hit towards the ray's origin, on top of anything scattered
+    fn emitted(&self, _r: &Ray<T>, _rec: &HitRecord<T>) -> Vector3<T>
+    where
+        T: cgmath::BaseFloat,
+    {
+        vec3(T::zero(), T::zero(), T::zero())
+    }
+
+    // the bsdf times the cosine at the surface for light leaving along `direction`. materials
+    // that return None (mirrors, glass) can't be lit by sampling lights directly
+    fn eval(
+        &self,
+        _r: &Ray<T>,
+        _rec: &HitRecord<T>,
+        _direction: &Vector3<T>,
+    ) -> Option<Vector3<T>> {
+        None
+    }
+
+    // the density of `scatter` picking `direction`, zero for materials without an `eval`
+    fn pdf(&self, _r: &Ray<T>, _rec: &HitRecord<T>, _direction: &Vector3<T>) -> T
+    where
+        T: cgmath::BaseFloat,
+    {
+        T::zero()
+    }
+}
+
+// looks up a texture at the hit's surface coordinates
+fn sample<T: cgmath::BaseFloat>(texture: &Rc<dyn Texture<T>>, rec: &HitRecord<T>) -> Vector3<T> {
+    let uv = rec.get_uv();
+    texture.value(uv.x, uv.y, rec.get_p())
 }
 
 pub struct Lambertian<T> {
-    albedo: Vector3<T>,
+    albedo: Rc<dyn Texture<T>>,
 }
 
-impl<T> Lambertian<T> {
+impl<T: cgmath::BaseFloat + 'static> Lambertian<T> {
     pub fn new(albedo: Vector3<T>) -> Self {
+        Self::textured(Rc::new(SolidColor::new(albedo)))
+    }
+}
+
+impl<T> Lambertian<T> {
+    pub fn textured(albedo: Rc<dyn Texture<T>>) -> Self {
         Self { albedo }
     }
 }
@@ -40,20 +103,46 @@ where
     T: cgmath::BaseFloat,
     Standard: Distribution<T>,
 {
-    fn scatter(&self, _r: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vector3<T>, Ray<T>)> {
-        let target = rec.get_p() + rec.get_normal() + rand_in_unit_sphere();
-        let scattered = Ray::new(*rec.get_p(), target - rec.get_p());
-        Some((self.albedo, scattered))
+    // cosine weighted, which cancels the cosine and 1/pi of the bsdf
+    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<ScatterRecord<T>> {
+        let normal = *rec.get_normal();
+        let mut direction = normal + rand_unit_vector();
+        if direction.magnitude2() < T::from(1e-8).unwrap() {
+            direction = normal;
+        }
+        let ray = Ray::with_time(*rec.get_p(), direction, r.time());
+        Some(ScatterRecord {
+            attenuation: sample(&self.albedo, rec),
+            pdf: Some(self.pdf(r, rec, &direction)),
+            ray,
+        })
+    }
+
+    fn eval(&self, _r: &Ray<T>, rec: &HitRecord<T>, direction: &Vector3<T>) -> Option<Vector3<T>> {
+        let cosine = rec.get_normal().dot(direction.normalize()).max(T::zero());
+        let pi = T::from(std::f64::consts::PI).unwrap();
+        Some(sample(&self.albedo, rec) * (cosine / pi))
+    }
+
+    fn pdf(&self, _r: &Ray<T>, rec: &HitRecord<T>, direction: &Vector3<T>) -> T {
+        let cosine = rec.get_normal().dot(direction.normalize()).max(T::zero());
+        cosine / T::from(std::f64::consts::PI).unwrap()
     }
 }
 
 pub struct Metal<T> {
-    albedo: Vector3<T>,
+    albedo: Rc<dyn Texture<T>>,
     fuzz: T,
 }
 
-impl<T> Metal<T> {
+impl<T: cgmath::BaseFloat + 'static> Metal<T> {
     pub fn new(albedo: Vector3<T>, fuzz: T) -> Self {
+        Self::textured(Rc::new(SolidColor::new(albedo)), fuzz)
+    }
+}
+
+impl<T> Metal<T> {
+    pub fn textured(albedo: Rc<dyn Texture<T>>, fuzz: T) -> Self {
         Self { albedo, fuzz }
     }
 }
@@ -70,15 +159,56 @@ where
     T: cgmath::BaseFloat,
     Standard: Distribution<T>,
 {
-    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vector3<T>, Ray<T>)> {
+    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<ScatterRecord<T>> {
         let reflected = reflect(r.direction().normalize(), *rec.get_normal());
-        let scattered = Ray::new(*rec.get_p(), reflected + rand_in_unit_sphere() * self.fuzz);
-        if scattered.direction().dot(*rec.get_normal()) > T::zero() {
-            Some((self.albedo, scattered))
+        let direction = reflected + rand_in_unit_sphere() * self.fuzz;
+        if direction.dot(*rec.get_normal()) > T::zero() {
+            Some(ScatterRecord {
+                attenuation: sample(&self.albedo, rec),
+                ray: Ray::with_time(*rec.get_p(), direction, r.time()),
+                pdf: if self.fuzz > T::zero() {
+                    Some(self.pdf(r, rec, &direction))
+                } else {
+                    None
+                },
+            })
         } else {
             None
         }
     }
+
+    // directions scattered below the surface are absorbed rather than redrawn, so the bsdf is
+    // just the albedo times the lobe's density
+    fn eval(&self, r: &Ray<T>, rec: &HitRecord<T>, direction: &Vector3<T>) -> Option<Vector3<T>> {
+        if self.fuzz > T::zero() {
+            Some(sample(&self.albedo, rec) * self.pdf(r, rec, direction))
+        } else {
+            None
+        }
+    }
+
+    // the reflection is offset by a uniform point in a ball of radius `fuzz`. the density of a
+    // direction is the ball's volume along that direction, the integral of t^2 dt over the chord
+    fn pdf(&self, r: &Ray<T>, rec: &HitRecord<T>, direction: &Vector3<T>) -> T {
+        let normal = *rec.get_normal();
+        let direction = direction.normalize();
+        if self.fuzz <= T::zero() || direction.dot(normal) <= T::zero() {
+            return T::zero();
+        }
+        let reflected = reflect(r.direction().normalize(), normal);
+        let b = direction.dot(reflected);
+        let discriminant = b * b - T::one() + self.fuzz * self.fuzz;
+        if discriminant <= T::zero() {
+            return T::zero();
+        }
+        let t1 = b + discriminant.sqrt();
+        let t0 = (b - discriminant.sqrt()).max(T::zero());
+        if t1 <= T::zero() {
+            return T::zero();
+        }
+        let four_pi = T::from(4.0 * std::f64::consts::PI).unwrap();
+        (t1 * t1 * t1 - t0 * t0 * t0) / (four_pi * self.fuzz * self.fuzz * self.fuzz)
+    }
 }
 
 pub struct Dielectric<T> {
@@ -117,35 +247,169 @@ where
     T: cgmath::BaseFloat,
     Standard: Distribution<T>,
 {
-    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<(Vector3<T>, Ray<T>)> {
-        let reflected = reflect(*r.direction(), *rec.get_normal());
+    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<ScatterRecord<T>> {
         let normal = *rec.get_normal();
+        let reflected = reflect(*r.direction(), normal);
 
-        let (outward_normal, ni_over_nt, cosine) = if r.direction().dot(normal) > T::zero() {
-            (
-                -normal,
-                self.ref_idx,
-                self.ref_idx * r.direction().dot(normal) / r.direction().magnitude(),
-            )
+        let cosine = -r.direction().dot(normal) / r.direction().magnitude();
+        let (ni_over_nt, cosine) = if rec.get_front_face() {
+            (T::one() / self.ref_idx, cosine)
         } else {
-            (
-                *rec.get_normal(),
-                T::one() / self.ref_idx,
-                -r.direction().dot(normal) / r.direction().magnitude(),
-            )
+            (self.ref_idx, self.ref_idx * cosine)
         };
 
-        let refracted = refract(r.direction(), &outward_normal, ni_over_nt);
+        let refracted = refract(r.direction(), &normal, ni_over_nt);
         let reflect_prob = match refracted {
             None => T::one(),
             Some(_) => schlick(cosine, self.ref_idx),
         };
 
-        let attenuation = vec3(T::one(), T::one(), T::one());
-        if thread_rng().gen::<T>() < reflect_prob {
-            Some((attenuation, Ray::new(*rec.get_p(), reflected)))
+        let direction = if thread_rng().gen::<T>() < reflect_prob {
+            reflected
         } else {
-            Some((attenuation, Ray::new(*rec.get_p(), refracted.unwrap())))
+            refracted.unwrap()
+        };
+        Some(ScatterRecord {
+            attenuation: vec3(T::one(), T::one(), T::one()),
+            ray: Ray::with_time(*rec.get_p(), direction, r.time()),
+            pdf: None,
+        })
+    }
+}
+
+// scatters uniformly in all directions, the phase function of participating media
+pub struct Isotropic<T> {
+    albedo: Rc<dyn Texture<T>>,
+}
+
+impl<T: cgmath::BaseFloat + 'static> Isotropic<T> {
+    pub fn new(albedo: Vector3<T>) -> Self {
+        Self::textured(Rc::new(SolidColor::new(albedo)))
+    }
+}
+
+impl<T> Isotropic<T> {
+    pub fn textured(albedo: Rc<dyn Texture<T>>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl<T> Material<T> for Isotropic<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    fn scatter(&self, r: &Ray<T>, rec: &HitRecord<T>) -> Option<ScatterRecord<T>> {
+        let direction = rand_in_unit_sphere();
+        Some(ScatterRecord {
+            attenuation: sample(&self.albedo, rec),
+            ray: Ray::with_time(*rec.get_p(), direction, r.time()),
+            pdf: Some(self.pdf(r, rec, &direction)),
+        })
+    }
+
+    fn eval(&self, _r: &Ray<T>, rec: &HitRecord<T>, _direction: &Vector3<T>) -> Option<Vector3<T>> {
+        let four_pi = T::from(4.0 * std::f64::consts::PI).unwrap();
+        Some(sample(&self.albedo, rec) / four_pi)
+    }
+
+    fn pdf(&self, _r: &Ray<T>, _rec: &HitRecord<T>, _direction: &Vector3<T>) -> T {
+        T::one() / T::from(4.0 * std::f64::consts::PI).unwrap()
+    }
+}
+
+// glows from its front faces and scatters nothing
+pub struct DiffuseLight<T> {
+    emit: Rc<dyn Texture<T>>,
+}
+
+impl<T: cgmath::BaseFloat + 'static> DiffuseLight<T> {
+    pub fn new(emit: Vector3<T>) -> Self {
+        Self::textured(Rc::new(SolidColor::new(emit)))
+    }
+}
+
+impl<T> DiffuseLight<T> {
+    pub fn textured(emit: Rc<dyn Texture<T>>) -> Self {
+        Self { emit }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Material<T> for DiffuseLight<T> {
+    fn scatter(&self, _r: &Ray<T>, _rec: &HitRecord<T>) -> Option<ScatterRecord<T>> {
+        None
+    }
+
+    fn emitted(&self, _r: &Ray<T>, rec: &HitRecord<T>) -> Vector3<T> {
+        if rec.get_front_face() {
+            sample(&self.emit, rec)
+        } else {
+            vec3(T::zero(), T::zero(), T::zero())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    // the share of a unit ball lying more than `h` below its center
+    fn cap(h: f64) -> f64 {
+        let c = (1.0 - h).max(0.0);
+        c * c * (3.0 - c) / 4.0
+    }
+
+    #[test]
+    fn metal_pdfs_integrate_to_what_isnt_absorbed() {
+        let normal = vec3(0.0, 1.0, 0.0);
+        for incoming in [vec3(1.0, -1.0, 0.0), vec3(1.0, -0.2, 0.0)].iter() {
+            let r = Ray::new(vec3(-1.0, 1.0, 0.0), *incoming);
+            let reflected = reflect(incoming.normalize(), normal);
+            // integrate in spherical coordinates around the reflection, where the lobe is
+            let onb = crate::Onb::from_w(reflected);
+            for fuzz in [0.1, 0.3, 1.0, 1.5].iter() {
+                let metal: Rc<dyn Material<f64>> = Rc::new(Metal::new(vec3(0.5, 0.5, 0.5), *fuzz));
+                let rec = HitRecord::new(&r, 1.0, vec3(0.0, 0.0, 0.0), normal, Rc::clone(&metal));
+                let (ntheta, nphi) = (2000, 256);
+                let (dtheta, dphi) = (PI / ntheta as f64, 2.0 * PI / nphi as f64);
+                let mut total = 0.0;
+                for i in 0..ntheta {
+                    let theta = (i as f64 + 0.5) * dtheta;
+                    for j in 0..nphi {
+                        let phi = (j as f64 + 0.5) * dphi;
+                        let d = onb.local(vec3(
+                            theta.sin() * phi.cos(),
+                            theta.sin() * phi.sin(),
+                            theta.cos(),
+                        ));
+                        total += metal.pdf(&r, &rec, &d) * theta.sin() * dtheta * dphi;
+                    }
+                }
+                let absorbed = cap(reflected.dot(normal) / fuzz);
+                assert!(
+                    (total - (1.0 - absorbed)).abs() < 0.005,
+                    "fuzz {}: {} vs {}",
+                    fuzz,
+                    total,
+                    1.0 - absorbed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lights_only_emit_from_their_front() {
+        let light: Rc<dyn Material<f64>> = Rc::new(DiffuseLight::new(vec3(4.0, 2.0, 1.0)));
+        let normal = vec3(0.0, 1.0, 0.0);
+        let above = Ray::new(vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0));
+        let rec = HitRecord::new(&above, 1.0, vec3(0.0, 0.0, 0.0), normal, Rc::clone(&light));
+        assert_eq!(light.emitted(&above, &rec), vec3(4.0, 2.0, 1.0));
+        assert!(light.scatter(&above, &rec).is_none());
+
+        let below = Ray::new(vec3(0.0, -1.0, 0.0), vec3(0.0, 1.0, 0.0));
+        let rec = HitRecord::new(&below, 1.0, vec3(0.0, 0.0, 0.0), normal, Rc::clone(&light));
+        assert_eq!(light.emitted(&below, &rec), vec3(0.0, 0.0, 0.0));
+        assert!(light.scatter(&below, &rec).is_none());
+    }
+}
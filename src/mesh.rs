@@ -0,0 +1,258 @@
+use super::triangle;
+use super::{Aabb, Bvh, HitRecord, HitTable, Material, Ray};
+use cgmath::{InnerSpace, Vector2, Vector3};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub enum MeshError {
+    IndexOutOfRange {
+        triangle: usize,
+        index: usize,
+    },
+    // a per-vertex attribute with a different length than `positions`
+    AttributeLength {
+        attribute: &'static str,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::IndexOutOfRange { triangle, index } => {
+                write!(
+                    f,
+                    "triangle {} refers to missing vertex {}",
+                    triangle, index
+                )
+            }
+            MeshError::AttributeLength {
+                attribute,
+                expected,
+                found,
+            } => write!(
+                f,
+                "mesh has {} vertices but {} {}",
+                expected, found, attribute
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+pub struct MeshData<T> {
+    pub positions: Vec<Vector3<T>>,
+    // per-vertex, indexed the same way as `positions`
+    pub normals: Option<Vec<Vector3<T>>>,
+    pub uvs: Option<Vec<Vector2<T>>>,
+    // linear rgb in [0, 1]
+    pub colors: Option<Vec<Vector3<T>>>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl<T: cgmath::BaseFloat> MeshData<T> {
+    // drops triangles with (nearly) zero area and returns their original indices
+    pub fn remove_degenerate(&mut self) -> Vec<usize> {
+        let positions = &self.positions;
+        let mut removed = vec![];
+        let mut index = 0;
+        self.indices.retain(|[a, b, c]| {
+            let e1 = positions[*b] - positions[*a];
+            let e2 = positions[*c] - positions[*a];
+            let degenerate =
+                e1.cross(e2).magnitude2() <= T::epsilon() * e1.magnitude2() * e2.magnitude2();
+            if degenerate {
+                removed.push(index);
+            }
+            index += 1;
+            !degenerate
+        });
+        removed
+    }
+}
+
+impl<T> MeshData<T> {
+    pub fn new(positions: Vec<Vector3<T>>, indices: Vec<[usize; 3]>) -> Self {
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+            colors: None,
+            indices,
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn vertices(&self, index: usize) -> [&Vector3<T>; 3] {
+        let [a, b, c] = self.indices[index];
+        [&self.positions[a], &self.positions[b], &self.positions[c]]
+    }
+
+    fn vertex_normals(&self, index: usize) -> Option<[&Vector3<T>; 3]> {
+        let [a, b, c] = self.indices[index];
+        self.normals
+            .as_ref()
+            .map(|normals| [&normals[a], &normals[b], &normals[c]])
+    }
+
+    fn vertex_uvs(&self, index: usize) -> Option<[&Vector2<T>; 3]> {
+        let [a, b, c] = self.indices[index];
+        self.uvs.as_ref().map(|uvs| [&uvs[a], &uvs[b], &uvs[c]])
+    }
+}
+
+struct MeshTriangle<T> {
+    mesh: Rc<MeshData<T>>,
+    index: usize,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for MeshTriangle<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let vertices = self.mesh.vertices(self.index);
+        let hit = triangle::intersect(vertices, r, &t)?;
+        Some(triangle::hit_record(
+            vertices,
+            self.mesh.vertex_normals(self.index),
+            self.mesh.vertex_uvs(self.index),
+            r,
+            hit,
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Aabb::from_points(self.mesh.vertices(self.index).iter().map(|v| **v))
+    }
+}
+
+pub struct TriangleMesh<T> {
+    data: Rc<MeshData<T>>,
+    bvh: Bvh<T>,
+}
+
+impl<T: cgmath::BaseFloat + 'static> TriangleMesh<T> {
+    pub fn new(data: MeshData<T>, material: Rc<dyn Material<T>>) -> Result<Self, MeshError> {
+        let vertex_count = data.positions.len();
+        for (triangle, indices) in data.indices.iter().enumerate() {
+            if let Some(index) = indices.iter().find(|i| **i >= vertex_count) {
+                return Err(MeshError::IndexOutOfRange {
+                    triangle,
+                    index: *index,
+                });
+            }
+        }
+        let lengths = [
+            ("normals", data.normals.as_ref().map(Vec::len)),
+            ("uvs", data.uvs.as_ref().map(Vec::len)),
+            ("colors", data.colors.as_ref().map(Vec::len)),
+        ];
+        for (attribute, len) in lengths.iter() {
+            match len {
+                Some(found) if *found != vertex_count => {
+                    return Err(MeshError::AttributeLength {
+                        attribute,
+                        expected: vertex_count,
+                        found: *found,
+                    })
+                }
+                _ => (),
+            }
+        }
+
+        let data = Rc::new(data);
+        let triangles = (0..data.triangle_count())
+            .map(|index| {
+                Box::new(MeshTriangle {
+                    mesh: Rc::clone(&data),
+                    index,
+                    material: Rc::clone(&material),
+                }) as Box<dyn HitTable<T>>
+            })
+            .collect();
+        Ok(Self {
+            bvh: Bvh::new(triangles),
+            data,
+        })
+    }
+
+    pub fn data(&self) -> &MeshData<T> {
+        &self.data
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for TriangleMesh<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        self.bvh.hit(r, t)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.bvh.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use cgmath::{vec2, vec3};
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    // a unit square in the xy plane, split along its diagonal
+    fn square() -> MeshData<f64> {
+        MeshData::new(
+            vec![
+                vec3(0.0, 0.0, 0.0),
+                vec3(1.0, 0.0, 0.0),
+                vec3(1.0, 1.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        )
+    }
+
+    #[test]
+    fn meshes_hit_every_triangle() {
+        let mesh = TriangleMesh::new(square(), material()).unwrap();
+        for &(x, y) in &[(0.7, 0.2), (0.2, 0.7)] {
+            let r = Ray::new(vec3(x, y, 1.0), vec3(0.0, 0.0, -1.0));
+            let hit = mesh.hit(&r, 0.001..10.0).unwrap();
+            assert!((hit.get_p() - vec3(x, y, 0.0)).magnitude() < 1e-12);
+        }
+        let r = Ray::new(vec3(1.5, 0.5, 1.0), vec3(0.0, 0.0, -1.0));
+        assert!(mesh.hit(&r, 0.001..10.0).is_none());
+    }
+
+    #[test]
+    fn meshes_reject_bad_indices_and_attributes() {
+        let mut data = square();
+        data.indices.push([1, 2, 4]);
+        assert_eq!(
+            TriangleMesh::new(data, material()).err(),
+            Some(MeshError::IndexOutOfRange {
+                triangle: 2,
+                index: 4
+            })
+        );
+        let mut data = square();
+        data.uvs = Some(vec![vec2(0.0, 0.0); 3]);
+        assert_eq!(
+            TriangleMesh::new(data, material()).err(),
+            Some(MeshError::AttributeLength {
+                attribute: "uvs",
+                expected: 4,
+                found: 3
+            })
+        );
+    }
+}
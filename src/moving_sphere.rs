@@ -0,0 +1,128 @@
+use super::csg::{Solid, Span};
+use super::sphere;
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::Vector3;
+use std::ops::Range;
+use std::rc::Rc;
+
+// a sphere travelling in a straight line from center0 at time0 to center1 at time1,
+// resting at either end outside of that interval
+pub struct MovingSphere<T> {
+    center0: Vector3<T>,
+    center1: Vector3<T>,
+    time0: T,
+    time1: T,
+    radius: T,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T> MovingSphere<T> {
+    pub fn new(
+        center0: Vector3<T>,
+        center1: Vector3<T>,
+        time0: T,
+        time1: T,
+        radius: T,
+        material: Rc<dyn Material<T>>,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> MovingSphere<T> {
+    pub fn center(&self, time: T) -> Vector3<T> {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let s = ((time - self.time0) / (self.time1 - self.time0))
+            .max(T::zero())
+            .min(T::one());
+        self.center0 + (self.center1 - self.center0) * s
+    }
+
+    fn hit_record(&self, r: &Ray<T>, t: T) -> HitRecord<T> {
+        let p = r.point_at_parameter(t);
+        let normal = (p - self.center(r.time())) / self.radius;
+        let mut rec = HitRecord::new(r, t, p, normal, Rc::clone(&self.material));
+        sphere::set_surface(&mut rec, normal);
+        rec
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for MovingSphere<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        sphere::intersect(self.center(r.time()), self.radius, r, &t)
+            .map(|temp| self.hit_record(r, temp))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let r = self.radius.abs();
+        let r = Vector3::new(r, r, r);
+        let start = Aabb::new(self.center0 - r, self.center0 + r);
+        let end = Aabb::new(self.center1 - r, self.center1 + r);
+        Some(start.surrounding(&end))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Solid<T> for MovingSphere<T> {
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>> {
+        sphere::roots(self.center(r.time()), self.radius, r)
+            .map(|(t0, t1)| Span {
+                enter: self.hit_record(r, t0),
+                exit: self.hit_record(r, t1),
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use cgmath::{vec3, InnerSpace};
+
+    fn sphere() -> MovingSphere<f64> {
+        MovingSphere::new(
+            vec3(0.0, 0.0, 0.0),
+            vec3(2.0, 1.0, 0.0),
+            1.0,
+            3.0,
+            0.5,
+            Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn centers_move_between_the_two_times() {
+        let sphere = sphere();
+        assert_eq!(sphere.center(1.0), vec3(0.0, 0.0, 0.0));
+        assert_eq!(sphere.center(2.0), vec3(1.0, 0.5, 0.0));
+        assert_eq!(sphere.center(3.0), vec3(2.0, 1.0, 0.0));
+        // resting outside the interval
+        assert_eq!(sphere.center(-5.0), vec3(0.0, 0.0, 0.0));
+        assert_eq!(sphere.center(9.0), vec3(2.0, 1.0, 0.0));
+
+        // the same ray hits wherever the sphere is at the ray's time
+        for &(time, center) in &[(1.0, vec3(0.0, 0.0, 0.0)), (2.0, vec3(1.0, 0.5, 0.0))] {
+            let r = Ray::with_time(center + vec3(0.0, 0.0, 5.0), vec3(0.0, 0.0, -1.0), time);
+            let rec = sphere.hit(&r, 0.0..f64::MAX).unwrap();
+            assert!((rec.get_t() - 4.5).abs() < 1e-9);
+            assert!((rec.get_normal() - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn bounds_cover_both_ends() {
+        let bounds = sphere().bounding_box().unwrap();
+        assert_eq!(*bounds.min(), vec3(-0.5, -0.5, -0.5));
+        assert_eq!(*bounds.max(), vec3(2.5, 1.5, 0.5));
+    }
+}
@@ -0,0 +1,148 @@
+use cgmath::{vec3, InnerSpace, Vector3};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+const POINT_COUNT: usize = 256;
+
+// gradient noise on the integer lattice, the same seed always gives the same noise
+pub struct Perlin<T> {
+    gradients: Vec<Vector3<T>>,
+    perm: [Vec<usize>; 3],
+}
+
+impl<T: cgmath::BaseFloat> Perlin<T> {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let v = vec3(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                );
+                let length2: f64 = v.magnitude2();
+                if length2 > 1e-6 && length2 <= 1.0 {
+                    let v = v / length2.sqrt();
+                    return vec3(
+                        T::from(v.x).unwrap(),
+                        T::from(v.y).unwrap(),
+                        T::from(v.z).unwrap(),
+                    );
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let perm = [permutation(), permutation(), permutation()];
+        Self { gradients, perm }
+    }
+
+    // roughly in [-1, 1], zero at every lattice point
+    pub fn noise(&self, p: Vector3<T>) -> T {
+        let floor = vec3(p.x.floor(), p.y.floor(), p.z.floor());
+        let f = p - floor;
+        // wrapping to the table size keeps negative coordinates in range
+        let cell = |x: T| x.to_i64().unwrap_or(0).rem_euclid(POINT_COUNT as i64) as usize;
+        let (i, j, k) = (cell(floor.x), cell(floor.y), cell(floor.z));
+        let smooth = |t: T| t * t * (T::from(3.0).unwrap() - T::from(2.0).unwrap() * t);
+        let (u, v, w) = (smooth(f.x), smooth(f.y), smooth(f.z));
+
+        let mut sum = T::zero();
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm[0][(i + di) % POINT_COUNT]
+                        ^ self.perm[1][(j + dj) % POINT_COUNT]
+                        ^ self.perm[2][(k + dk) % POINT_COUNT]];
+                    let corner = vec3(
+                        T::from(di).unwrap(),
+                        T::from(dj).unwrap(),
+                        T::from(dk).unwrap(),
+                    );
+                    let weight = |c: T, t: T| c * t + (T::one() - c) * (T::one() - t);
+                    sum += weight(corner.x, u)
+                        * weight(corner.y, v)
+                        * weight(corner.z, w)
+                        * gradient.dot(f - corner);
+                }
+            }
+        }
+        sum
+    }
+
+    // fractal brownian motion, octaves of noise each at double the frequency and half the
+    // amplitude of the last
+    pub fn fbm(&self, p: Vector3<T>, octaves: usize) -> T {
+        let half = T::from(0.5).unwrap();
+        let mut sum = T::zero();
+        let (mut frequency, mut amplitude) = (T::one(), T::one());
+        for _ in 0..octaves {
+            sum += self.noise(p * frequency) * amplitude;
+            frequency += frequency;
+            amplitude *= half;
+        }
+        sum
+    }
+
+    // like fbm but summing absolute values, which gives sharp creases where the noise crosses zero
+    pub fn turbulence(&self, p: Vector3<T>, octaves: usize) -> T {
+        let half = T::from(0.5).unwrap();
+        let mut sum = T::zero();
+        let (mut frequency, mut amplitude) = (T::one(), T::one());
+        for _ in 0..octaves {
+            sum += self.noise(p * frequency).abs() * amplitude;
+            frequency += frequency;
+            amplitude *= half;
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<Vector3<f64>> {
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..2000)
+            .map(|_| {
+                vec3(
+                    rng.gen_range(-300.0, 300.0),
+                    rng.gen_range(-300.0, 300.0),
+                    rng.gen_range(-300.0, 300.0),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeds_decide_the_noise() {
+        let (a, b, other) = (Perlin::new(42), Perlin::new(42), Perlin::new(43));
+        let mut differences = 0;
+        for p in points() {
+            assert_eq!(a.noise(p), b.noise(p));
+            assert_eq!(a.fbm(p, 5), b.fbm(p, 5));
+            if (a.noise(p) - other.noise(p)).abs() > 1e-6 {
+                differences += 1;
+            }
+        }
+        assert!(differences > 1900, "{}", differences);
+    }
+
+    #[test]
+    fn noise_stays_in_bounds() {
+        let noise = Perlin::new(7);
+        for p in points() {
+            assert!(noise.noise(p).abs() <= 1.0);
+            assert!(noise.fbm(p, 7).abs() <= 2.0);
+            let turbulence = noise.turbulence(p, 7);
+            assert!((0.0..2.0).contains(&turbulence));
+            // every lattice point is a zero crossing
+            let corner = vec3(p.x.floor(), p.y.floor(), p.z.floor());
+            assert!(noise.noise(corner).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::onb::Onb;
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::{InnerSpace, Vector2, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+// the angle of (x, y) around the origin as a fraction of a full turn, in [0, 1)
+pub(crate) fn turns<T: cgmath::BaseFloat>(y: T, x: T) -> T {
+    let turns = y.atan2(x) / (T::from(2.0 * std::f64::consts::PI).unwrap());
+    if turns < T::zero() {
+        turns + T::one()
+    } else {
+        turns
+    }
+}
+
+// intersection with the plane through `point` with normal `normal`
+fn intersect<T: cgmath::BaseFloat>(
+    point: Vector3<T>,
+    normal: Vector3<T>,
+    r: &Ray<T>,
+    t: &Range<T>,
+) -> Option<T> {
+    let denom = normal.dot(*r.direction());
+    if denom.abs() <= T::epsilon() {
+        return None;
+    }
+    let temp = (point - r.origin()).dot(normal) / denom;
+    if temp > t.start && temp < t.end {
+        Some(temp)
+    } else {
+        None
+    }
+}
+
+// an infinite plane, uv is the distance along the plane's own axes from `point`
+pub struct Plane<T> {
+    point: Vector3<T>,
+    basis: Onb<T>,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T: cgmath::BaseFloat> Plane<T> {
+    pub fn new(point: Vector3<T>, normal: Vector3<T>, material: Rc<dyn Material<T>>) -> Self {
+        Self {
+            point,
+            basis: Onb::from_w(normal),
+            material,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Plane<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let normal = *self.basis.w();
+        let temp = intersect(self.point, normal, r, &t)?;
+        let p = r.point_at_parameter(temp);
+        let d = p - self.point;
+        let mut rec = HitRecord::new(r, temp, p, normal, Rc::clone(&self.material));
+        rec.set_uv(Vector2::new(d.dot(*self.basis.u()), d.dot(*self.basis.v())));
+        rec.set_tangents(*self.basis.u(), *self.basis.v());
+        Some(rec)
+    }
+}
+
+// u goes around the center, v outwards from it
+pub struct Disk<T> {
+    center: Vector3<T>,
+    radius: T,
+    basis: Onb<T>,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T: cgmath::BaseFloat> Disk<T> {
+    pub fn new(
+        center: Vector3<T>,
+        normal: Vector3<T>,
+        radius: T,
+        material: Rc<dyn Material<T>>,
+    ) -> Self {
+        Self {
+            center,
+            radius,
+            basis: Onb::from_w(normal),
+            material,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Disk<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let normal = *self.basis.w();
+        let temp = intersect(self.center, normal, r, &t)?;
+        let p = r.point_at_parameter(temp);
+        let d = p - self.center;
+        let distance = d.magnitude();
+        if distance > self.radius {
+            return None;
+        }
+        let mut rec = HitRecord::new(r, temp, p, normal, Rc::clone(&self.material));
+        rec.set_uv(Vector2::new(
+            turns(d.dot(*self.basis.v()), d.dot(*self.basis.u())),
+            distance / self.radius,
+        ));
+        rec.set_tangents(d.cross(normal), d);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        // the disk's extent along each axis is radius * sin of the angle between axis and normal
+        let n = self.basis.w();
+        let extent = Vector3::new(
+            (T::one() - n.x * n.x).max(T::zero()).sqrt(),
+            (T::one() - n.y * n.y).max(T::zero()).sqrt(),
+            (T::one() - n.z * n.z).max(T::zero()).sqrt(),
+        ) * self.radius
+            + Vector3::new(T::one(), T::one(), T::one()) * T::from(0.0001).unwrap();
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use cgmath::vec3;
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn plane_hits_sampled_points() {
+        let normal = vec3(1.0, 2.0, -0.5).normalize();
+        let point = vec3(0.3, -1.0, 2.0);
+        let plane = Plane::new(point, normal, material());
+        let basis = Onb::from_w(normal);
+        for i in 0..16 {
+            let (a, b) = (f64::from(i) * 0.7 - 5.0, f64::from(i * i) * 0.1 - 3.0);
+            let target = point + basis.u() * a + basis.v() * b;
+            let origin = target + vec3(0.2, 1.0, 0.1) * 3.0;
+            let r = Ray::new(origin, target - origin);
+            let rec = plane.hit(&r, 0.0..f64::MAX).unwrap();
+            assert!((rec.get_t() - 1.0).abs() < 1e-9);
+            assert!((rec.get_p() - target).magnitude() < 1e-9);
+            assert!((rec.get_normal() - normal).magnitude() < 1e-9);
+            assert!((rec.get_uv() - Vector2::new(a, b)).magnitude() < 1e-9);
+        }
+        let parallel = Ray::new(point + normal, *basis.u());
+        assert!(plane.hit(&parallel, 0.0..f64::MAX).is_none());
+    }
+
+    #[test]
+    fn disk_hits_sampled_points() {
+        let normal = vec3(0.0, 0.0, 1.0);
+        let disk = Disk::new(vec3(1.0, 1.0, 1.0), normal, 2.0, material());
+        let basis = Onb::from_w(normal);
+        for i in 0..16 {
+            let u = f64::from(i) / 16.0;
+            let v = 0.1 + f64::from(i) * 0.05;
+            let phi = u * 2.0 * std::f64::consts::PI;
+            let target =
+                vec3(1.0, 1.0, 1.0) + (basis.u() * phi.cos() + basis.v() * phi.sin()) * 2.0 * v;
+            let r = Ray::new(target + normal * 4.0, -normal);
+            let rec = disk.hit(&r, 0.0..f64::MAX).unwrap();
+            assert!((rec.get_t() - 4.0).abs() < 1e-9);
+            assert!((rec.get_p() - target).magnitude() < 1e-9);
+            assert!((rec.get_normal() - normal).magnitude() < 1e-9);
+            assert!((rec.get_uv() - Vector2::new(u, v)).magnitude() < 1e-9);
+            let bounds = disk.bounding_box().unwrap();
+            assert!(bounds.contains(&target));
+        }
+        let outside = Ray::new(vec3(3.5, 1.0, 5.0), -normal);
+        assert!(disk.hit(&outside, 0.0..f64::MAX).is_none());
+    }
+}
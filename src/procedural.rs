@@ -0,0 +1,165 @@
+use super::{Perlin, Texture};
+use cgmath::{vec3, Vector3};
+
+const OCTAVES: usize = 7;
+
+// colors at positions along [0, 1], linearly blended in between and held past the ends
+pub struct ColorRamp<T> {
+    stops: Vec<(T, Vector3<T>)>,
+}
+
+impl<T: cgmath::BaseFloat> ColorRamp<T> {
+    pub fn new(mut stops: Vec<(T, Vector3<T>)>) -> Self {
+        assert!(!stops.is_empty(), "color ramps need at least one stop");
+        assert!(
+            stops.iter().all(|(position, _)| !position.is_nan()),
+            "color ramp stops can't be at NaN"
+        );
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Self { stops }
+    }
+
+    pub fn sample(&self, t: T) -> Vector3<T> {
+        let next = self.stops.iter().position(|(position, _)| *position > t);
+        match next {
+            Some(0) => self.stops[0].1,
+            None => self.stops[self.stops.len() - 1].1,
+            Some(i) => {
+                let (p0, c0) = self.stops[i - 1];
+                let (p1, c1) = self.stops[i];
+                c0 + (c1 - c0) * ((t - p0) / (p1 - p0))
+            }
+        }
+    }
+}
+
+// veins running along z, bent by turbulence
+pub struct Marble<T> {
+    noise: Perlin<T>,
+    scale: T,
+    turbulence: T,
+    ramp: ColorRamp<T>,
+}
+
+impl<T: cgmath::BaseFloat> Marble<T> {
+    pub fn new(seed: u64, scale: T, ramp: ColorRamp<T>) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            turbulence: T::from(10.0).unwrap(),
+            ramp,
+        }
+    }
+
+    // how far the veins wander from straight lines
+    pub fn set_turbulence(&mut self, turbulence: T) {
+        self.turbulence = turbulence;
+    }
+}
+
+impl<T: cgmath::BaseFloat> Texture<T> for Marble<T> {
+    fn value(&self, _u: T, _v: T, p: &Vector3<T>) -> Vector3<T> {
+        let p = p * self.scale;
+        let phase = p.z + self.turbulence * self.noise.turbulence(p, OCTAVES);
+        let half = T::from(0.5).unwrap();
+        self.ramp.sample(half + half * phase.sin())
+    }
+}
+
+// concentric rings around the y axis, the ramp runs from one ring to the next
+pub struct Wood<T> {
+    noise: Perlin<T>,
+    scale: T,
+    distortion: T,
+    ramp: ColorRamp<T>,
+}
+
+impl<T: cgmath::BaseFloat> Wood<T> {
+    pub fn new(seed: u64, scale: T, ramp: ColorRamp<T>) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            distortion: T::from(0.3).unwrap(),
+            ramp,
+        }
+    }
+
+    // how much the rings wobble, in ring widths
+    pub fn set_distortion(&mut self, distortion: T) {
+        self.distortion = distortion;
+    }
+}
+
+impl<T: cgmath::BaseFloat> Texture<T> for Wood<T> {
+    fn value(&self, _u: T, _v: T, p: &Vector3<T>) -> Vector3<T> {
+        let p = p * self.scale;
+        // stretch the noise along the grain
+        let grain = vec3(p.x, p.y * T::from(0.25).unwrap(), p.z);
+        let radius = (p.x * p.x + p.z * p.z).sqrt() + self.distortion * self.noise.fbm(grain, 4);
+        self.ramp.sample(radius - radius.floor())
+    }
+}
+
+// fine speckles of several minerals
+pub struct Granite<T> {
+    noise: Perlin<T>,
+    scale: T,
+    ramp: ColorRamp<T>,
+}
+
+impl<T: cgmath::BaseFloat> Granite<T> {
+    pub fn new(seed: u64, scale: T, ramp: ColorRamp<T>) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            ramp,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Texture<T> for Granite<T> {
+    fn value(&self, _u: T, _v: T, p: &Vector3<T>) -> Vector3<T> {
+        // turbulence sums to at most about 2 but mostly stays well under 1
+        let t = self.noise.turbulence(p * self.scale, OCTAVES);
+        self.ramp.sample(t.min(T::one()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(c: f64) -> Vector3<f64> {
+        vec3(c, c, c)
+    }
+
+    #[test]
+    fn ramps_blend_between_stops_and_hold_past_them() {
+        // stops don't need to be given in order
+        let ramp = ColorRamp::new(vec![
+            (0.8, gray(1.0)),
+            (0.2, gray(0.0)),
+            (0.4, vec3(0.0, 1.0, 0.5)),
+        ]);
+        assert_eq!(ramp.sample(-1.0), gray(0.0));
+        assert_eq!(ramp.sample(0.2), gray(0.0));
+        assert_eq!(ramp.sample(0.3).x, 0.0);
+        assert!((ramp.sample(0.3).y - 0.5).abs() < 1e-12);
+        assert!((ramp.sample(0.3).z - 0.25).abs() < 1e-12);
+        assert_eq!(ramp.sample(0.4), vec3(0.0, 1.0, 0.5));
+        assert!((ramp.sample(0.7).x - 0.75).abs() < 1e-12);
+        assert!((ramp.sample(0.7).z - 0.875).abs() < 1e-12);
+        assert_eq!(ramp.sample(0.8), gray(1.0));
+        assert_eq!(ramp.sample(3.0), gray(1.0));
+
+        let single = ColorRamp::new(vec![(0.5, gray(0.25))]);
+        assert_eq!(single.sample(0.0), gray(0.25));
+        assert_eq!(single.sample(1.0), gray(0.25));
+    }
+
+    #[test]
+    #[should_panic(expected = "NaN")]
+    fn ramps_reject_nan_stops() {
+        ColorRamp::new(vec![(0.0, gray(0.0)), (std::f64::NAN, gray(1.0))]);
+    }
+}
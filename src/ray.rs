@@ -5,11 +5,20 @@ use cgmath::Vector3;
 pub struct Ray<T> {
     a: Vector3<T>,
     b: Vector3<T>,
+    time: T,
 }
 
 impl<T: cgmath::BaseNum> Ray<T> {
     pub fn new(a: Vector3<T>, b: Vector3<T>) -> Self {
-        Ray { a, b }
+        Ray {
+            a,
+            b,
+            time: T::zero(),
+        }
+    }
+
+    pub fn with_time(a: Vector3<T>, b: Vector3<T>, time: T) -> Self {
+        Ray { a, b, time }
     }
 
     pub fn origin(&self) -> &Vector3<T> {
@@ -20,6 +29,10 @@ impl<T: cgmath::BaseNum> Ray<T> {
         &self.b
     }
 
+    pub fn time(&self) -> T {
+        self.time
+    }
+
     pub fn point_at_parameter(&self, t: T) -> Vector3<T> {
         self.a + self.b * t
     }
@@ -0,0 +1,379 @@
+use super::csg::{Solid, Span};
+use super::{Aabb, AreaLight, HitRecord, HitTable, HitTableList, Material, Ray};
+use cgmath::{InnerSpace, Vector2, Vector3};
+use rand::distributions::Standard;
+use rand::prelude::*;
+use std::ops::Range;
+use std::rc::Rc;
+
+// rects have no thickness, so their boxes are padded along the normal to stay hittable
+fn padding<T: cgmath::BaseFloat>() -> T {
+    T::from(0.0001).unwrap()
+}
+
+// intersects the plane `axis = k` and checks the other two coordinates against the rect
+fn hit_rect<T: cgmath::BaseFloat>(
+    r: &Ray<T>,
+    t: &Range<T>,
+    (a, b, axis): (usize, usize, usize),
+    (a0, a1, b0, b1, k): (T, T, T, T, T),
+    material: &Rc<dyn Material<T>>,
+) -> Option<HitRecord<T>> {
+    let temp = (k - r.origin()[axis]) / r.direction()[axis];
+    if !(temp > t.start && temp < t.end) {
+        return None;
+    }
+    let p = r.point_at_parameter(temp);
+    if p[a] < a0 || p[a] > a1 || p[b] < b0 || p[b] > b1 {
+        return None;
+    }
+    let zero = Vector3::new(T::zero(), T::zero(), T::zero());
+    let (mut normal, mut dpdu, mut dpdv) = (zero, zero, zero);
+    normal[axis] = T::one();
+    dpdu[a] = T::one();
+    dpdv[b] = T::one();
+    let mut rec = HitRecord::new(r, temp, p, normal, Rc::clone(material));
+    rec.set_uv(Vector2::new(
+        (p[a] - a0) / (a1 - a0),
+        (p[b] - b0) / (b1 - b0),
+    ));
+    rec.set_tangents(dpdu, dpdv);
+    Some(rec)
+}
+
+// a uniformly random point on the rect, as seen from `origin`
+fn sample_rect<T>(
+    origin: &Vector3<T>,
+    (a, b, axis): (usize, usize, usize),
+    (a0, a1, b0, b1, k): (T, T, T, T, T),
+) -> Vector3<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    let mut rng = thread_rng();
+    let mut p = Vector3::new(T::zero(), T::zero(), T::zero());
+    p[a] = a0 + (a1 - a0) * rng.gen::<T>();
+    p[b] = b0 + (b1 - b0) * rng.gen::<T>();
+    p[axis] = k;
+    p - origin
+}
+
+// the area density of `sample_rect` converted to solid angle
+fn rect_pdf<T: cgmath::BaseFloat>(
+    origin: &Vector3<T>,
+    direction: &Vector3<T>,
+    (a, b, axis): (usize, usize, usize),
+    (a0, a1, b0, b1, k): (T, T, T, T, T),
+) -> T {
+    let t = (k - origin[axis]) / direction[axis];
+    let p = origin + direction * t;
+    if !(t > T::zero() && p[a] >= a0 && p[a] <= a1 && p[b] >= b0 && p[b] <= b1) {
+        return T::zero();
+    }
+    let distance2 = t * t * direction.magnitude2();
+    let cosine = direction[axis].abs() / direction.magnitude();
+    distance2 / (cosine * (a1 - a0) * (b1 - b0))
+}
+
+macro_rules! axis_rect {
+    ($name:ident, $a:ident, $b:ident, ($ai:expr, $bi:expr, $ki:expr)) => {
+        pub struct $name<T> {
+            $a: (T, T),
+            $b: (T, T),
+            k: T,
+            material: Rc<dyn Material<T>>,
+        }
+
+        impl<T> $name<T> {
+            pub fn new($a: (T, T), $b: (T, T), k: T, material: Rc<dyn Material<T>>) -> Self {
+                Self {
+                    $a,
+                    $b,
+                    k,
+                    material,
+                }
+            }
+        }
+
+        impl<T: cgmath::BaseFloat> HitTable<T> for $name<T> {
+            fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+                hit_rect(
+                    r,
+                    &t,
+                    ($ai, $bi, $ki),
+                    (self.$a.0, self.$a.1, self.$b.0, self.$b.1, self.k),
+                    &self.material,
+                )
+            }
+
+            fn bounding_box(&self) -> Option<Aabb<T>> {
+                let mut min = Vector3::new(T::zero(), T::zero(), T::zero());
+                let mut max = min;
+                min[$ai] = self.$a.0;
+                max[$ai] = self.$a.1;
+                min[$bi] = self.$b.0;
+                max[$bi] = self.$b.1;
+                min[$ki] = self.k - padding();
+                max[$ki] = self.k + padding();
+                Some(Aabb::new(min, max))
+            }
+        }
+
+        impl<T> AreaLight<T> for $name<T>
+        where
+            T: cgmath::BaseFloat,
+            Standard: Distribution<T>,
+        {
+            fn sample(&self, origin: &Vector3<T>) -> Vector3<T> {
+                sample_rect(
+                    origin,
+                    ($ai, $bi, $ki),
+                    (self.$a.0, self.$a.1, self.$b.0, self.$b.1, self.k),
+                )
+            }
+
+            fn pdf(&self, origin: &Vector3<T>, direction: &Vector3<T>) -> T {
+                rect_pdf(
+                    origin,
+                    direction,
+                    ($ai, $bi, $ki),
+                    (self.$a.0, self.$a.1, self.$b.0, self.$b.1, self.k),
+                )
+            }
+        }
+    };
+}
+
+// each rect spans two axes at a fixed value of the third, facing towards +third axis
+axis_rect!(XyRect, x, y, (0, 1, 2));
+axis_rect!(XzRect, x, z, (0, 2, 1));
+axis_rect!(YzRect, y, z, (1, 2, 0));
+
+// turns geometry inside out, e.g. for walls that should face into a room
+pub struct FlipFace<T> {
+    object: Box<dyn HitTable<T>>,
+}
+
+impl<T> FlipFace<T> {
+    pub fn new(object: Box<dyn HitTable<T>>) -> Self {
+        Self { object }
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for FlipFace<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let mut rec = self.object.hit(r, t)?;
+        let front_face = rec.get_front_face();
+        rec.set_front_face(!front_face);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        self.object.bounding_box()
+    }
+}
+
+pub struct BoxShape<T> {
+    min: Vector3<T>,
+    max: Vector3<T>,
+    sides: HitTableList<T>,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T: cgmath::BaseFloat + 'static> BoxShape<T> {
+    pub fn new(p0: Vector3<T>, p1: Vector3<T>, material: Rc<dyn Material<T>>) -> Self {
+        let m = || Rc::clone(&material);
+        let mut sides = HitTableList::new();
+        sides.add(Box::new(XyRect::new((p0.x, p1.x), (p0.y, p1.y), p1.z, m())));
+        sides.add(Box::new(FlipFace::new(Box::new(XyRect::new(
+            (p0.x, p1.x),
+            (p0.y, p1.y),
+            p0.z,
+            m(),
+        )))));
+        sides.add(Box::new(XzRect::new((p0.x, p1.x), (p0.z, p1.z), p1.y, m())));
+        sides.add(Box::new(FlipFace::new(Box::new(XzRect::new(
+            (p0.x, p1.x),
+            (p0.z, p1.z),
+            p0.y,
+            m(),
+        )))));
+        sides.add(Box::new(YzRect::new((p0.y, p1.y), (p0.z, p1.z), p1.x, m())));
+        sides.add(Box::new(FlipFace::new(Box::new(YzRect::new(
+            (p0.y, p1.y),
+            (p0.z, p1.z),
+            p0.x,
+            m(),
+        )))));
+        Self {
+            min: p0,
+            max: p1,
+            sides,
+            material,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for BoxShape<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        self.sides.hit(r, t)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+impl<T: cgmath::BaseFloat> BoxShape<T> {
+    // the outward normal of whichever face `p` lies closest to
+    fn face_record(&self, r: &Ray<T>, t: T) -> HitRecord<T> {
+        let p = r.point_at_parameter(t);
+        let mut normal = Vector3::new(T::zero(), T::zero(), T::zero());
+        let mut nearest = T::infinity();
+        for axis in 0..3 {
+            for &(distance, sign) in &[
+                ((p[axis] - self.min[axis]).abs(), -T::one()),
+                ((self.max[axis] - p[axis]).abs(), T::one()),
+            ] {
+                if distance < nearest {
+                    nearest = distance;
+                    normal = Vector3::new(T::zero(), T::zero(), T::zero());
+                    normal[axis] = sign;
+                }
+            }
+        }
+        HitRecord::new(r, t, p, normal, Rc::clone(&self.material))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Solid<T> for BoxShape<T> {
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>> {
+        Aabb::new(self.min, self.max)
+            .intersect(r, T::neg_infinity()..T::infinity())
+            .map(|span| Span {
+                enter: self.face_record(r, span.start),
+                exit: self.face_record(r, span.end),
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use cgmath::vec3;
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn rects_hit_inside_their_bounds_with_uvs_across_them() {
+        let xy = XyRect::new((1.0, 3.0), (2.0, 4.0), 5.0, material());
+        let towards = |x, y| Ray::new(vec3(x, y, 0.0), vec3(0.0, 0.0, 1.0));
+        let rec = xy.hit(&towards(1.5, 3.5), 0.0..f64::MAX).unwrap();
+        assert_eq!(rec.get_t(), 5.0);
+        assert_eq!(*rec.get_uv(), Vector2::new(0.25, 0.75));
+        assert_eq!(*rec.get_normal(), vec3(0.0, 0.0, -1.0));
+        assert!(!rec.get_front_face());
+        // the edges belong to the rect
+        let rec = xy.hit(&towards(1.0, 4.0), 0.0..f64::MAX).unwrap();
+        assert_eq!(*rec.get_uv(), Vector2::new(0.0, 1.0));
+        assert!(xy.hit(&towards(0.99, 3.0), 0.0..f64::MAX).is_none());
+        assert!(xy.hit(&towards(2.0, 4.01), 0.0..f64::MAX).is_none());
+        assert!(xy.hit(&towards(2.0, 3.0), 0.0..5.0).is_none());
+
+        let xz = XzRect::new((0.0, 2.0), (0.0, 4.0), 1.0, material());
+        let rec = xz
+            .hit(
+                &Ray::new(vec3(0.5, 3.0, 1.0), vec3(0.0, -1.0, 0.0)),
+                0.0..f64::MAX,
+            )
+            .unwrap();
+        assert_eq!(rec.get_t(), 2.0);
+        assert_eq!(*rec.get_uv(), Vector2::new(0.25, 0.25));
+        assert!(rec.get_front_face());
+
+        let yz = YzRect::new((-1.0, 1.0), (0.0, 1.0), -2.0, material());
+        let rec = yz
+            .hit(
+                &Ray::new(vec3(-4.0, 0.5, 0.75), vec3(1.0, 0.0, 0.0)),
+                0.0..f64::MAX,
+            )
+            .unwrap();
+        assert_eq!(rec.get_t(), 2.0);
+        assert_eq!(*rec.get_uv(), Vector2::new(0.75, 0.75));
+        assert!(yz
+            .hit(
+                &Ray::new(vec3(-4.0, 0.5, 1.5), vec3(1.0, 0.0, 0.0)),
+                0.0..f64::MAX
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn flipping_turns_the_rect_around() {
+        let r = Ray::new(vec3(0.5, 0.5, -1.0), vec3(0.0, 0.0, 1.0));
+        let rect = XyRect::new((0.0, 1.0), (0.0, 1.0), 0.0, material());
+        let rec = rect.hit(&r, 0.0..f64::MAX).unwrap();
+        assert!(!rec.get_front_face());
+        let flipped = FlipFace::new(Box::new(rect));
+        let flipped_rec = flipped.hit(&r, 0.0..f64::MAX).unwrap();
+        assert!(flipped_rec.get_front_face());
+        assert_eq!(flipped_rec.get_t(), rec.get_t());
+        assert_eq!(flipped_rec.get_normal(), rec.get_normal());
+    }
+
+    // rays at the middle of each face of the box from (0, 0, 0) to (1, 2, 3), from outside
+    fn face_rays() -> Vec<(Ray<f64>, Vector3<f64>, f64)> {
+        let center = vec3(0.5, 1.0, 1.5);
+        let half = vec3(0.5, 1.0, 1.5);
+        let mut rays = vec![];
+        for axis in 0..3 {
+            for &sign in &[-1.0, 1.0] {
+                let mut outward = vec3(0.0, 0.0, 0.0);
+                outward[axis] = sign;
+                let origin = center + outward * (half[axis] + 2.0);
+                rays.push((Ray::new(origin, -outward), outward, 2.0));
+            }
+        }
+        rays
+    }
+
+    #[test]
+    fn boxes_hit_every_face_from_outside() {
+        let shape = BoxShape::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 2.0, 3.0), material());
+        for (r, outward, t) in face_rays() {
+            let rec = shape.hit(&r, 0.0..f64::MAX).unwrap();
+            assert_eq!(rec.get_t(), t);
+            assert!(rec.get_front_face(), "{:?}", outward);
+            assert_eq!(*rec.get_normal(), outward);
+        }
+    }
+
+    #[test]
+    fn box_spans_enter_and_leave_through_opposite_faces() {
+        let shape = BoxShape::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 2.0, 3.0), material());
+        for (r, outward, t) in face_rays() {
+            let spans = shape.spans(&r);
+            assert_eq!(spans.len(), 1);
+            let Span { enter, exit } = &spans[0];
+            assert_eq!(enter.get_t(), t);
+            assert!(enter.get_front_face());
+            assert_eq!(*enter.get_normal(), outward);
+            // leaving, the stored normal faces back against the ray
+            assert!(!exit.get_front_face());
+            assert_eq!(*exit.get_normal(), outward);
+        }
+
+        // a diagonal ray leaves through the side it reaches first
+        let r = Ray::new(vec3(-1.0, 0.5, 1.5), vec3(1.0, 1.0, 0.0));
+        let spans = shape.spans(&r);
+        assert_eq!(*spans[0].enter.get_normal(), vec3(-1.0, 0.0, 0.0));
+        assert!((spans[0].exit.get_t() - 1.5).abs() < 1e-12);
+        assert_eq!(*spans[0].exit.get_normal(), vec3(0.0, -1.0, 0.0));
+    }
+}
@@ -0,0 +1,370 @@
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::{vec2, vec3, InnerSpace, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+const MAX_STEPS: usize = 512;
+
+fn c<T: cgmath::BaseFloat>(x: f64) -> T {
+    T::from(x).unwrap()
+}
+
+// a signed distance field, negative inside. `distance` may underestimate but never overestimate
+// the distance to the surface, otherwise sphere tracing steps through it
+pub trait Sdf<T> {
+    fn distance(&self, p: Vector3<T>) -> T;
+
+    // the analytic gradient, where there is a cheap one. objects fall back to central differences
+    fn gradient(&self, _p: Vector3<T>) -> Option<Vector3<T>> {
+        None
+    }
+}
+
+pub struct SdfSphere<T> {
+    center: Vector3<T>,
+    radius: T,
+}
+
+impl<T> SdfSphere<T> {
+    pub fn new(center: Vector3<T>, radius: T) -> Self {
+        Self { center, radius }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Sdf<T> for SdfSphere<T> {
+    fn distance(&self, p: Vector3<T>) -> T {
+        (p - self.center).magnitude() - self.radius
+    }
+
+    fn gradient(&self, p: Vector3<T>) -> Option<Vector3<T>> {
+        let d = p - self.center;
+        if d.magnitude2() > T::zero() {
+            Some(d.normalize())
+        } else {
+            None
+        }
+    }
+}
+
+// a box with its edges rounded off by `radius`, a radius of zero gives a sharp box.
+// `half_extents` include the rounding
+pub struct SdfRoundBox<T> {
+    center: Vector3<T>,
+    half_extents: Vector3<T>,
+    radius: T,
+}
+
+impl<T: cgmath::BaseFloat> SdfRoundBox<T> {
+    pub fn new(center: Vector3<T>, half_extents: Vector3<T>, radius: T) -> Self {
+        Self {
+            center,
+            half_extents,
+            radius,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Sdf<T> for SdfRoundBox<T> {
+    fn distance(&self, p: Vector3<T>) -> T {
+        let inner = self.half_extents - vec3(self.radius, self.radius, self.radius);
+        let p = p - self.center;
+        let q = vec3(p.x.abs(), p.y.abs(), p.z.abs()) - inner;
+        let outside = vec3(q.x.max(T::zero()), q.y.max(T::zero()), q.z.max(T::zero()));
+        outside.magnitude() + q.x.max(q.y).max(q.z).min(T::zero()) - self.radius
+    }
+}
+
+pub struct SdfBox<T>(SdfRoundBox<T>);
+
+impl<T: cgmath::BaseFloat> SdfBox<T> {
+    pub fn new(center: Vector3<T>, half_extents: Vector3<T>) -> Self {
+        SdfBox(SdfRoundBox::new(center, half_extents, T::zero()))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Sdf<T> for SdfBox<T> {
+    fn distance(&self, p: Vector3<T>) -> T {
+        self.0.distance(p)
+    }
+}
+
+// a ring in the xz plane, like `Torus`
+pub struct SdfTorus<T> {
+    center: Vector3<T>,
+    major: T,
+    minor: T,
+}
+
+impl<T> SdfTorus<T> {
+    pub fn new(center: Vector3<T>, major: T, minor: T) -> Self {
+        Self {
+            center,
+            major,
+            minor,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Sdf<T> for SdfTorus<T> {
+    fn distance(&self, p: Vector3<T>) -> T {
+        let p = p - self.center;
+        vec2(vec2(p.x, p.z).magnitude() - self.major, p.y).magnitude() - self.minor
+    }
+
+    fn gradient(&self, p: Vector3<T>) -> Option<Vector3<T>> {
+        let p = p - self.center;
+        let ring = vec3(p.x, T::zero(), p.z);
+        if ring.magnitude2() == T::zero() {
+            return None;
+        }
+        let d = p - ring.normalize() * self.major;
+        if d.magnitude2() > T::zero() {
+            Some(d.normalize())
+        } else {
+            None
+        }
+    }
+}
+
+// blends two fields together over a distance of about `k`
+pub struct SmoothUnion<T> {
+    a: Box<dyn Sdf<T>>,
+    b: Box<dyn Sdf<T>>,
+    k: T,
+}
+
+impl<T> SmoothUnion<T> {
+    pub fn new(a: Box<dyn Sdf<T>>, b: Box<dyn Sdf<T>>, k: T) -> Self {
+        Self { a, b, k }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Sdf<T> for SmoothUnion<T> {
+    fn distance(&self, p: Vector3<T>) -> T {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        if self.k <= T::zero() {
+            return a.min(b);
+        }
+        let h = (c::<T>(0.5) + c::<T>(0.5) * (b - a) / self.k)
+            .max(T::zero())
+            .min(T::one());
+        b + (a - b) * h - self.k * h * (T::one() - h)
+    }
+}
+
+// tiles space with copies of a field centered on the origin, `period` apart along each axis.
+// an axis with a period of zero isn't repeated
+pub struct Repeat<T> {
+    inner: Box<dyn Sdf<T>>,
+    period: Vector3<T>,
+}
+
+impl<T> Repeat<T> {
+    pub fn new(inner: Box<dyn Sdf<T>>, period: Vector3<T>) -> Self {
+        Self { inner, period }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Sdf<T> for Repeat<T> {
+    fn distance(&self, p: Vector3<T>) -> T {
+        let mut q = p;
+        for axis in 0..3 {
+            let period = self.period[axis];
+            if period > T::zero() {
+                q[axis] = p[axis] - period * (p[axis] / period).round();
+            }
+        }
+        self.inner.distance(q)
+    }
+}
+
+// twists a field around the y axis by `rate` radians per unit of height. this stretches
+// distances, so objects holding a twist need a step scale below one
+pub struct Twist<T> {
+    inner: Box<dyn Sdf<T>>,
+    rate: T,
+}
+
+impl<T> Twist<T> {
+    pub fn new(inner: Box<dyn Sdf<T>>, rate: T) -> Self {
+        Self { inner, rate }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Sdf<T> for Twist<T> {
+    fn distance(&self, p: Vector3<T>) -> T {
+        let (s, c) = (self.rate * p.y).sin_cos();
+        self.inner
+            .distance(vec3(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+    }
+}
+
+// renders a field by sphere tracing it inside `bounds`, which must enclose the whole surface
+pub struct SdfObject<T> {
+    sdf: Box<dyn Sdf<T>>,
+    bounds: Aabb<T>,
+    material: Rc<dyn Material<T>>,
+    epsilon: T,
+    step_scale: T,
+}
+
+impl<T: cgmath::BaseFloat> SdfObject<T> {
+    pub fn new(sdf: Box<dyn Sdf<T>>, bounds: Aabb<T>, material: Rc<dyn Material<T>>) -> Self {
+        Self {
+            sdf,
+            bounds,
+            material,
+            epsilon: c(0.0001),
+            step_scale: T::one(),
+        }
+    }
+
+    // how close to the surface counts as a hit
+    pub fn set_epsilon(&mut self, epsilon: T) {
+        self.epsilon = epsilon;
+    }
+
+    // shortens every step, for fields that overestimate distances like `Twist`
+    pub fn set_step_scale(&mut self, step_scale: T) {
+        self.step_scale = step_scale;
+    }
+
+    fn normal(&self, p: Vector3<T>) -> Vector3<T> {
+        if let Some(gradient) = self.sdf.gradient(p) {
+            return gradient.normalize();
+        }
+        let h = self.epsilon;
+        let axis = |x, y, z| {
+            let offset = vec3(x, y, z) * h;
+            self.sdf.distance(p + offset) - self.sdf.distance(p - offset)
+        };
+        vec3(
+            axis(T::one(), T::zero(), T::zero()),
+            axis(T::zero(), T::one(), T::zero()),
+            axis(T::zero(), T::zero(), T::one()),
+        )
+        .normalize()
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for SdfObject<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let span = self.bounds.intersect(r, t)?;
+        let length = r.direction().magnitude();
+        let mut temp = span.start;
+        // a secondary ray leaving the surface starts within epsilon of it, and at a grazing
+        // angle would find it again straight away, so it must get clear before it can hit
+        let mut leaving = self.sdf.distance(r.point_at_parameter(temp)).abs() < self.epsilon;
+        for _ in 0..MAX_STEPS {
+            let p = r.point_at_parameter(temp);
+            // the absolute distance lets rays that start inside march out to the surface too
+            let distance = self.sdf.distance(p).abs();
+            if leaving {
+                leaving = distance < self.epsilon;
+                temp += distance.max(self.epsilon) * self.step_scale / length;
+                if temp >= span.end {
+                    return None;
+                }
+                continue;
+            }
+            if distance < self.epsilon {
+                return Some(HitRecord::new(
+                    r,
+                    temp,
+                    p,
+                    self.normal(p),
+                    Rc::clone(&self.material),
+                ));
+            }
+            temp += distance * self.step_scale / length;
+            if temp >= span.end {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Lambertian, Sphere};
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn traced_sphere_matches_the_analytic_one() {
+        let center = vec3(0.5, -0.25, 1.0);
+        let object = SdfObject::new(
+            Box::new(SdfSphere::new(center, 0.75)),
+            Aabb::new(center - vec3(1.0, 1.0, 1.0), center + vec3(1.0, 1.0, 1.0)),
+            material(),
+        );
+        let sphere = Sphere::new(center, 0.75, material());
+        for i in 0..32 {
+            let x = f64::from(i) / 32.0 - 0.5;
+            let r = Ray::new(vec3(x, x * 0.5, -4.0), center - vec3(x, x * 0.5, -4.0));
+            let expected = sphere.hit(&r, 0.0..f64::MAX).unwrap();
+            let rec = object.hit(&r, 0.0..f64::MAX).unwrap();
+            assert!((rec.get_p() - expected.get_p()).magnitude() < 1e-3);
+            assert!((rec.get_normal() - expected.get_normal()).magnitude() < 1e-3);
+        }
+        let miss = Ray::new(vec3(5.0, 0.0, -4.0), vec3(0.0, 0.0, 1.0));
+        assert!(object.hit(&miss, 0.0..f64::MAX).is_none());
+    }
+
+    #[test]
+    fn grazing_rays_leave_the_surface() {
+        let object = SdfObject::new(
+            Box::new(SdfSphere::new(vec3(0.0, 0.0, 0.0), 1.0)),
+            Aabb::new(vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0)),
+            material(),
+        );
+        let top = vec3(0.0, 1.0, 0.0);
+        // skimming away from the top of the sphere finds nothing
+        let away = Ray::new(top, vec3(1.0, 0.001, 0.0));
+        assert!(object.hit(&away, 0.001..f64::MAX).is_none());
+        // skimming into it comes out the far side of a short chord, not where it started
+        let into = Ray::new(top, vec3(1.0, -0.1, 0.0));
+        let rec = object.hit(&into, 0.001..f64::MAX).unwrap();
+        assert!((rec.get_t() - 0.2 / 1.01).abs() < 1e-3);
+    }
+
+    #[test]
+    fn building_blocks_measure_distances() {
+        let sharp = SdfBox::<f64>::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 2.0, 3.0));
+        assert!((sharp.distance(vec3(2.0, 0.0, 0.0)) - 1.0).abs() < 1e-12);
+        assert!((sharp.distance(vec3(0.0, 0.0, 0.0)) + 1.0).abs() < 1e-12);
+        assert!((sharp.distance(vec3(2.0, 3.0, 0.0)) - 2f64.sqrt()).abs() < 1e-12);
+
+        let round = SdfRoundBox::<f64>::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0), 0.5);
+        let corner = vec3(1.0, 1.0, 1.0).normalize() * (0.5 + 0.5 * 3f64.sqrt());
+        assert!(round.distance(corner).abs() < 1e-12);
+
+        let torus = SdfTorus::<f64>::new(vec3(0.0, 0.0, 0.0), 2.0, 0.5);
+        assert!(torus.distance(vec3(2.5, 0.0, 0.0)).abs() < 1e-12);
+        assert!((torus.distance(vec3(0.0, 0.0, 0.0)) - 1.5).abs() < 1e-12);
+
+        let tiled = Repeat::<f64>::new(
+            Box::new(SdfSphere::new(vec3(0.0, 0.0, 0.0), 0.5)),
+            vec3(4.0, 0.0, 0.0),
+        );
+        assert!(tiled.distance(vec3(8.5, 0.0, 0.0)).abs() < 1e-12);
+        assert!((tiled.distance(vec3(8.0, 1.0, 0.0)) - 0.5).abs() < 1e-12);
+
+        // far from the seam a smooth union is just the nearer of the two
+        let blend = SmoothUnion::<f64>::new(
+            Box::new(SdfSphere::new(vec3(-2.0, 0.0, 0.0), 1.0)),
+            Box::new(SdfSphere::new(vec3(2.0, 0.0, 0.0), 1.0)),
+            0.25,
+        );
+        assert!((blend.distance(vec3(-4.0, 0.0, 0.0)) - 1.0).abs() < 1e-12);
+        assert!(blend.distance(vec3(0.0, 0.0, 0.0)) < 1.0);
+    }
+}
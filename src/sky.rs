@@ -0,0 +1,345 @@
+// The Preetham, Shirley and Smits analytic daylight model ("A Practical Analytic Model for
+// Daylight", 1999) with a sun disk. The model works in luminance (kcd/m^2) and chromaticity,
+// which are converted to linear rgb and scaled by the sky's intensity. Directions use +y for up,
+// +x for east and -z for north.
+use super::{AreaLight, Environment, Onb};
+use cgmath::{vec3, ElementWise, InnerSpace, Vector3};
+use rand::distributions::Standard;
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+// the sun's angular radius as seen from the earth
+const SUN_RADIUS: f64 = 0.004_65;
+// roughly the sun's luminance outside the atmosphere, in kcd/m^2
+const SUN_LUMINANCE: f64 = 2.0e6;
+// how far below the horizon, in degrees, the sun sinks before the sky goes black. this is the
+// end of civil twilight, and the model has nothing to say about the night sky past it
+const TWILIGHT: f64 = 6.0;
+
+// the perez distribution's five coefficients for one of luminance or the two chromaticities
+type Perez = [f64; 5];
+
+fn perez(p: &Perez, cos_theta: f64, gamma: f64) -> f64 {
+    // the model is only fitted above the horizon
+    let cos_theta = cos_theta.max(1e-3);
+    (1.0 + p[0] * (p[1] / cos_theta).exp())
+        * (1.0 + p[2] * (p[3] * gamma).exp() + p[4] * gamma.cos() * gamma.cos())
+}
+
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vector3<f64> {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    vec3(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
+
+fn cubic(c: [f64; 4], t: f64) -> f64 {
+    ((c[0] * t + c[1]) * t + c[2]) * t + c[3]
+}
+
+pub struct PreethamSky<T> {
+    sun_direction: Vector3<T>,
+    turbidity: T,
+    ground_albedo: Vector3<T>,
+    intensity: T,
+    // everything below is derived from the parameters above
+    perez: [Perez; 3],
+    zenith: [f64; 3],
+    sun: Vector3<f64>,
+    ground: Vector3<f64>,
+    daylight: f64,
+}
+
+impl<T: cgmath::BaseFloat> PreethamSky<T> {
+    // turbidity runs from about 2 for a very clear sky to 10 for a hazy one
+    pub fn new(sun_direction: Vector3<T>, turbidity: T, ground_albedo: Vector3<T>) -> Self {
+        let mut sky = Self {
+            sun_direction: sun_direction.normalize(),
+            turbidity,
+            ground_albedo,
+            // puts the zenith of a clear sky at noon somewhere around 0.5
+            intensity: T::from(0.05).unwrap(),
+            perez: [[0.0; 5]; 3],
+            zenith: [0.0; 3],
+            sun: vec3(0.0, 0.0, 0.0),
+            ground: vec3(0.0, 0.0, 0.0),
+            daylight: 1.0,
+        };
+        sky.update();
+        sky
+    }
+
+    pub fn set_sun_direction(&mut self, sun_direction: Vector3<T>) {
+        self.sun_direction = sun_direction.normalize();
+        self.update();
+    }
+
+    // places the sun for local solar time in hours, a latitude in degrees (north is positive)
+    // and a day of the year from 1 to 365
+    pub fn set_time_of_day(&mut self, hours: T, latitude: T, day: u32) {
+        let declination =
+            (-23.44f64).to_radians() * (2.0 * PI * (f64::from(day) + 10.0) / 365.0).cos();
+        let hour_angle = (15.0 * (hours.to_f64().unwrap() - 12.0)).to_radians();
+        let latitude = latitude.to_f64().unwrap().to_radians();
+        let east = -declination.cos() * hour_angle.sin();
+        let north = declination.sin() * latitude.cos()
+            - declination.cos() * hour_angle.cos() * latitude.sin();
+        let up = declination.sin() * latitude.sin()
+            + declination.cos() * hour_angle.cos() * latitude.cos();
+        self.set_sun_direction(vec3(
+            T::from(east).unwrap(),
+            T::from(up).unwrap(),
+            T::from(-north).unwrap(),
+        ));
+    }
+
+    pub fn set_turbidity(&mut self, turbidity: T) {
+        self.turbidity = turbidity;
+        self.update();
+    }
+
+    pub fn set_ground_albedo(&mut self, ground_albedo: Vector3<T>) {
+        self.ground_albedo = ground_albedo;
+        self.update();
+    }
+
+    // scales everything the sky gives off
+    pub fn set_intensity(&mut self, intensity: T) {
+        self.intensity = intensity;
+    }
+
+    pub fn sun_direction(&self) -> &Vector3<T> {
+        &self.sun_direction
+    }
+
+    fn sun_f64(&self) -> Vector3<f64> {
+        self.sun_direction.cast().unwrap()
+    }
+
+    fn update(&mut self) {
+        let t = self.turbidity.to_f64().unwrap();
+        let sun = self.sun_f64();
+        // below the horizon the sky keeps its sunset colors but fades out through twilight
+        let theta_s = sun.y.max(0.0).acos();
+        let elevation = sun.y.clamp(-1.0, 1.0).asin().to_degrees();
+        self.daylight = (1.0 + elevation / TWILIGHT).clamp(0.0, 1.0);
+
+        self.perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0], theta_s)
+            + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394], theta_s)
+            + cubic([0.11693, -0.21196, 0.06052, 0.25886], theta_s);
+        let y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0], theta_s)
+            + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516], theta_s)
+            + cubic([0.15346, -0.26756, 0.06670, 0.26688], theta_s);
+        // normalized so evaluating the distribution gives the zenith values straight up
+        self.zenith = [
+            luminance / perez(&self.perez[0], 1.0, theta_s),
+            x / perez(&self.perez[1], 1.0, theta_s),
+            y / perez(&self.perez[2], 1.0, theta_s),
+        ];
+
+        // rayleigh and aerosol extinction along the sun's path through the atmosphere, at a
+        // wavelength (in micrometres) for each of red, green and blue
+        self.sun = if sun.y > 0.0 {
+            let zenith_degrees = theta_s.to_degrees();
+            let mass = 1.0 / (sun.y + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+            let beta = 0.046_083_658_220_5 * t - 0.045_860_259_285_2;
+            let transmittance = |lambda: f64| {
+                let rayleigh = (-0.008_735 * lambda.powf(-4.08) * mass).exp();
+                let aerosol = (-beta * lambda.powf(-1.3) * mass).exp();
+                SUN_LUMINANCE * rayleigh * aerosol
+            };
+            vec3(
+                transmittance(0.65),
+                transmittance(0.55),
+                transmittance(0.45),
+            )
+        } else {
+            vec3(0.0, 0.0, 0.0)
+        };
+
+        // the ground is lambertian, lit by the sky and sun over it
+        let (n_theta, n_phi) = (32, 64);
+        let mut irradiance = vec3(0.0, 0.0, 0.0);
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) / n_theta as f64 * PI / 2.0;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) / n_phi as f64 * 2.0 * PI;
+                let d = vec3(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let weight = theta.cos()
+                    * theta.sin()
+                    * (PI / 2.0 / n_theta as f64)
+                    * (2.0 * PI / n_phi as f64);
+                irradiance += self.sky(d) * weight;
+            }
+        }
+        let sun_solid_angle = 2.0 * PI * (1.0 - Self::cos_sun_radius());
+        irradiance += self.sun * (sun.y.max(0.0) * sun_solid_angle);
+        let albedo: Vector3<f64> = self.ground_albedo.cast().unwrap();
+        self.ground = albedo.mul_element_wise(irradiance) / PI;
+    }
+
+    // the sky without the sun, unscaled, for a direction above the horizon
+    fn sky(&self, d: Vector3<f64>) -> Vector3<f64> {
+        let gamma = d.dot(self.sun_f64()).clamp(-1.0, 1.0).acos();
+        let value = |i: usize| self.zenith[i] * perez(&self.perez[i], d.y, gamma);
+        xyy_to_rgb(value(1), value(2), value(0))
+    }
+
+    fn cos_sun_radius() -> f64 {
+        SUN_RADIUS.cos()
+    }
+}
+
+impl<T: cgmath::BaseFloat> Environment<T> for PreethamSky<T> {
+    fn radiance(&self, direction: &Vector3<T>) -> Vector3<T> {
+        let d: Vector3<f64> = direction.normalize().cast().unwrap();
+        let rgb = if d.y < 0.0 {
+            self.ground
+        } else if d.dot(self.sun_f64()) >= Self::cos_sun_radius() {
+            self.sky(d) + self.sun
+        } else {
+            self.sky(d)
+        };
+        let rgb = rgb * self.daylight;
+        let to_t = |v: f64| T::from(v.max(0.0)).unwrap();
+        vec3(to_t(rgb.x), to_t(rgb.y), to_t(rgb.z)) * self.intensity
+    }
+}
+
+// only the sun is sampled, the rest of the sky is smooth enough to be found by bounces
+impl<T> AreaLight<T> for PreethamSky<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    fn sample(&self, _origin: &Vector3<T>) -> Vector3<T> {
+        let mut rng = thread_rng();
+        let height = 1.0 - Self::cos_sun_radius();
+        let cos_theta = 1.0 - rng.gen::<f64>() * height;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let local = vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+        Onb::from_w(self.sun_direction).local(local.cast().unwrap())
+    }
+
+    fn pdf(&self, _origin: &Vector3<T>, direction: &Vector3<T>) -> T {
+        let d: Vector3<f64> = direction.normalize().cast().unwrap();
+        if d.dot(self.sun_f64()) >= Self::cos_sun_radius() {
+            T::from(1.0 / (2.0 * PI * (1.0 - Self::cos_sun_radius()))).unwrap()
+        } else {
+            T::zero()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sky() -> PreethamSky<f64> {
+        PreethamSky::new(vec3(0.3, 0.8, -0.2), 3.0, vec3(0.3, 0.3, 0.3))
+    }
+
+    #[test]
+    fn sun_follows_the_time_of_day() {
+        let mut sky = sky();
+        // on the equator at an equinox the sun rises due east and is overhead at noon
+        sky.set_time_of_day(12.0, 0.0, 80);
+        assert!(sky.sun_direction().y > 0.99);
+        sky.set_time_of_day(6.0, 0.0, 80);
+        let sun = *sky.sun_direction();
+        assert!(sun.x > 0.99 && sun.y.abs() < 0.02);
+        // in the northern summer the noon sun is to the south
+        sky.set_time_of_day(12.0, 50.0, 172);
+        assert!(sky.sun_direction().z > 0.0);
+    }
+
+    #[test]
+    fn zenith_is_blue_and_dimmer_than_the_sun_as_is_the_ground() {
+        let sky = sky();
+        let zenith = sky.radiance(&vec3(0.0, 1.0, 0.0));
+        assert!(zenith.z > zenith.x);
+        assert!(zenith.x > 0.0 && zenith.x.is_finite());
+        let sun = sky.radiance(sky.sun_direction());
+        assert!(sun.x > 1000.0 * zenith.x);
+        let ground = sky.radiance(&vec3(0.0, -1.0, 0.0));
+        assert!(ground.x > 0.0 && ground.x < sun.x);
+    }
+
+    #[test]
+    fn sky_fades_to_black_after_sunset() {
+        let mut sky = sky();
+        let brightness = |sky: &PreethamSky<f64>| {
+            [
+                vec3(0.0, 1.0, 0.0),
+                vec3(0.6, 0.1, 0.0),
+                vec3(-0.6, 0.1, 0.0),
+                vec3(0.0, -1.0, 0.0),
+            ]
+            .iter()
+            .map(|d| sky.radiance(d))
+            .fold(0.0, |sum, c| sum + c.x + c.y + c.z)
+        };
+        let below = |degrees: f64| {
+            let angle = -degrees.to_radians();
+            vec3(angle.cos(), angle.sin(), 0.0)
+        };
+        sky.set_sun_direction(below(0.0));
+        let sunset = brightness(&sky);
+        sky.set_sun_direction(below(3.0));
+        let dusk = brightness(&sky);
+        sky.set_sun_direction(below(6.5));
+        let night = brightness(&sky);
+        assert!(sunset > 0.0);
+        assert!(dusk > 0.0 && dusk < sunset);
+        assert_eq!(night, 0.0);
+
+        // midnight in midsummer at a mid northern latitude
+        sky.set_time_of_day(0.0, 40.0, 172);
+        assert_eq!(brightness(&sky), 0.0);
+    }
+
+    #[test]
+    fn sun_samples_land_on_the_sun() {
+        let sky = sky();
+        let origin = vec3(0.0, 0.0, 0.0);
+        for _ in 0..100 {
+            let d = sky.sample(&origin);
+            assert!(sky.pdf(&origin, &d) > 0.0);
+            assert!(sky.radiance(&d).x > sky.radiance(&vec3(0.0, 1.0, 0.0)).x * 1000.0);
+        }
+        assert_eq!(sky.pdf(&origin, &vec3(0.0, 1.0, 0.0)), 0.0);
+    }
+}
@@ -1,7 +1,10 @@
 extern crate cgmath;
 
-use super::{HitRecord, HitTable, Material, Ray};
+use super::csg::{Solid, Span};
+use super::{Aabb, AreaLight, HitRecord, HitTable, Material, Onb, Ray};
 use cgmath::InnerSpace;
+use rand::distributions::Standard;
+use rand::prelude::*;
 use std::ops::Range;
 use std::rc::Rc;
 
@@ -22,32 +25,231 @@ impl<T> Sphere<T> {
     }
 }
 
-impl<T: cgmath::BaseNum> Sphere<T> {
+impl<T: cgmath::BaseFloat> Sphere<T> {
     pub fn hit_record(&self, ray: &Ray<T>, t: T) -> HitRecord<T> {
         let p = ray.point_at_parameter(t);
         let normal = (p - self.center) / self.radius;
-        HitRecord::new(t, p, normal, Rc::clone(&self.material))
+        let mut rec = HitRecord::new(ray, t, p, normal, Rc::clone(&self.material));
+        set_surface(&mut rec, normal);
+        rec
     }
 }
 
+// spherical coordinates of the point at `n` on the unit sphere, u goes around the y axis
+// starting from -x and v from the bottom pole to the top one
+pub(crate) fn set_surface<T: cgmath::BaseFloat>(rec: &mut HitRecord<T>, n: cgmath::Vector3<T>) {
+    let pi = T::from(std::f64::consts::PI).unwrap();
+    let phi = (-n.z).atan2(n.x) + pi;
+    let theta = (-n.y).max(-T::one()).min(T::one()).acos();
+    rec.set_uv(cgmath::Vector2::new(
+        phi / (T::from(2.0).unwrap() * pi),
+        theta / pi,
+    ));
+    // both derivatives vanish at the poles, where the record keeps its default frame
+    rec.set_tangents(
+        cgmath::Vector3::new(n.z, T::zero(), -n.x),
+        cgmath::Vector3::new(-n.x * n.y, T::one() - n.y * n.y, -n.z * n.y),
+    );
+}
+
+// both roots of the ray/sphere quadratic, nearest first
+pub(crate) fn roots<T: cgmath::BaseFloat>(
+    center: cgmath::Vector3<T>,
+    radius: T,
+    r: &Ray<T>,
+) -> Option<(T, T)> {
+    let oc = r.origin() - center;
+    let a = r.direction().magnitude2();
+    let b = oc.dot(*r.direction());
+    let c = oc.magnitude2() - radius * radius;
+    let discriminant = b * b - a * c;
+    // todo: making 0 a constant would be an improvement https://github.com/rust-num/num-traits/issues/54
+    if discriminant > T::zero() {
+        Some((
+            (-b - discriminant.sqrt()) / a,
+            (-b + discriminant.sqrt()) / a,
+        ))
+    } else {
+        None
+    }
+}
+
+// returns the nearest root of the ray/sphere quadratic inside `t`
+pub(crate) fn intersect<T: cgmath::BaseFloat>(
+    center: cgmath::Vector3<T>,
+    radius: T,
+    r: &Ray<T>,
+    t: &Range<T>,
+) -> Option<T> {
+    let (t0, t1) = roots(center, radius, r)?;
+    if t0 < t.end && t0 > t.start {
+        return Some(t0);
+    }
+    if t1 < t.end && t1 > t.start {
+        return Some(t1);
+    }
+    None
+}
+
 impl<T: cgmath::BaseFloat> HitTable<T> for Sphere<T> {
     fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
-        let oc = r.origin() - self.center;
-        let a = r.direction().magnitude2();
-        let b = oc.dot(*r.direction());
-        let c = oc.magnitude2() - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        // todo: making 0 a constant would be an improvement https://github.com/rust-num/num-traits/issues/54
-        if discriminant > T::zero() {
-            let temp = (-b - discriminant.sqrt()) / a;
-            if temp < t.end && temp > t.start {
-                return Some(self.hit_record(r, temp));
+        intersect(self.center, self.radius, r, &t).map(|temp| self.hit_record(r, temp))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let r = self.radius.abs();
+        let r = cgmath::Vector3::new(r, r, r);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Solid<T> for Sphere<T> {
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>> {
+        roots(self.center, self.radius, r)
+            .map(|(t0, t1)| Span {
+                enter: self.hit_record(r, t0),
+                exit: self.hit_record(r, t1),
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+// 1 - cos of the half angle of the cone a sphere covers, written to keep precision for distant
+// spheres
+fn cone_height<T: cgmath::BaseFloat>(radius2: T, distance2: T) -> T {
+    let sin2 = radius2 / distance2;
+    sin2 / (T::one() + (T::one() - sin2).sqrt())
+}
+
+impl<T> AreaLight<T> for Sphere<T>
+where
+    T: cgmath::BaseFloat,
+    Standard: Distribution<T>,
+{
+    // uniform over the cone of directions the sphere covers, or over all directions from inside
+    fn sample(&self, origin: &cgmath::Vector3<T>) -> cgmath::Vector3<T> {
+        let mut rng = thread_rng();
+        let two = T::from(2.0).unwrap();
+        let phi = two * T::from(std::f64::consts::PI).unwrap() * rng.gen::<T>();
+        let to_center = self.center - origin;
+        let distance2 = to_center.magnitude2();
+        let radius2 = self.radius * self.radius;
+        let (cos_theta, axis) = if distance2 <= radius2 {
+            (T::one() - two * rng.gen::<T>(), cgmath::Vector3::unit_z())
+        } else {
+            let height = cone_height(radius2, distance2);
+            (T::one() - rng.gen::<T>() * height, to_center)
+        };
+        let sin_theta = (T::one() - cos_theta * cos_theta).max(T::zero()).sqrt();
+        Onb::from_w(axis).local(cgmath::Vector3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ))
+    }
+
+    fn pdf(&self, origin: &cgmath::Vector3<T>, direction: &cgmath::Vector3<T>) -> T {
+        let four_pi = T::from(4.0 * std::f64::consts::PI).unwrap();
+        let distance2 = (self.center - origin).magnitude2();
+        let radius2 = self.radius * self.radius;
+        if distance2 <= radius2 {
+            return T::one() / four_pi;
+        }
+        let r = Ray::new(*origin, *direction);
+        if intersect(self.center, self.radius, &r, &(T::zero()..T::infinity())).is_none() {
+            return T::zero();
+        }
+        let two_pi = T::from(2.0 * std::f64::consts::PI).unwrap();
+        T::one() / (two_pi * cone_height(radius2, distance2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use cgmath::{vec2, vec3, Vector2, Vector3};
+    use std::f64::consts::PI;
+
+    fn sphere() -> Sphere<f64> {
+        Sphere::new(
+            vec3(1.0, 2.0, -1.0),
+            2.0,
+            Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))),
+        )
+    }
+
+    // the point on the unit sphere at (u, v), following the convention in `set_surface`
+    fn at(u: f64, v: f64) -> Vector3<f64> {
+        let (phi, theta) = (2.0 * PI * u, PI * v);
+        vec3(
+            -theta.sin() * phi.cos(),
+            -theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn hit_at(sphere: &Sphere<f64>, n: Vector3<f64>) -> HitRecord<f64> {
+        let target = vec3(1.0, 2.0, -1.0) + n * 2.0;
+        sphere
+            .hit(&Ray::new(target + n * 3.0, -n), 0.0..f64::MAX)
+            .unwrap()
+    }
+
+    #[test]
+    fn uvs_go_around_from_minus_x_and_up_from_the_bottom() {
+        let sphere = sphere();
+        let cases: [(Vector3<f64>, Vector2<f64>); 5] = [
+            (vec3(0.0, 0.0, 1.0), vec2(0.25, 0.5)),
+            (vec3(1.0, 0.0, 0.0), vec2(0.5, 0.5)),
+            (vec3(0.0, 0.0, -1.0), vec2(0.75, 0.5)),
+            (vec3(0.0, -1.0, 0.0), vec2(0.5, 0.0)),
+            (vec3(0.0, 1.0, 0.0), vec2(0.5, 1.0)),
+        ];
+        for &(n, uv) in &cases {
+            let rec = hit_at(&sphere, n);
+            assert!((rec.get_uv().y - uv.y).abs() < 1e-9);
+            // u is arbitrary at the poles
+            if n.y.abs() < 1.0 {
+                assert!((rec.get_uv().x - uv.x).abs() < 1e-9);
             }
-            let temp = (-b + discriminant.sqrt()) / a;
-            if temp < t.end && temp > t.start {
-                return Some(self.hit_record(r, temp));
+        }
+        // just short of a full turn, back towards -x
+        let rec = hit_at(&sphere, at(0.99, 0.3));
+        assert!((rec.get_uv() - vec2(0.99, 0.3)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn frames_follow_the_surface_derivatives() {
+        let sphere = sphere();
+        let h = 1e-6;
+        for i in 0..16 {
+            let (u, v) = ((f64::from(i) + 0.5) / 16.0, f64::from(i) / 15.0 * 0.8 + 0.1);
+            let rec = hit_at(&sphere, at(u, v));
+            assert!((rec.get_uv() - vec2(u, v)).magnitude() < 1e-9);
+            let (t, b, n) = (*rec.get_tangent(), *rec.get_bitangent(), *rec.get_normal());
+            for &(x, y) in &[(t, b), (b, n), (n, t)] {
+                assert!(x.dot(y).abs() < 1e-9);
             }
+            for &x in &[t, b, n] {
+                assert!((x.magnitude() - 1.0).abs() < 1e-9);
+            }
+            let dpdu = (at(u + h, v) - at(u - h, v)).normalize();
+            let dpdv = (at(u, v + h) - at(u, v - h)).normalize();
+            assert!((t - dpdu).magnitude() < 1e-6);
+            assert!((b - dpdv).magnitude() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn poles_keep_the_default_frame() {
+        let sphere = sphere();
+        for &n in &[vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0)] {
+            let rec = hit_at(&sphere, n);
+            let frame = Onb::from_w(n);
+            assert!((rec.get_tangent() - frame.u()).magnitude() < 1e-9);
+            assert!((rec.get_bitangent() - frame.v()).magnitude() < 1e-9);
         }
-        None
     }
 }
@@ -0,0 +1,274 @@
+use cgmath::{vec3, Vector3};
+use std::path::Path;
+use std::rc::Rc;
+
+pub trait Texture<T> {
+    fn value(&self, u: T, v: T, p: &Vector3<T>) -> Vector3<T>;
+}
+
+pub struct SolidColor<T> {
+    color: Vector3<T>,
+}
+
+impl<T> SolidColor<T> {
+    pub fn new(color: Vector3<T>) -> Self {
+        Self { color }
+    }
+}
+
+impl<T: Copy> Texture<T> for SolidColor<T> {
+    fn value(&self, _u: T, _v: T, _p: &Vector3<T>) -> Vector3<T> {
+        self.color
+    }
+}
+
+enum CheckerSpace<T> {
+    // cells of size `scale` filling world space
+    Solid(T),
+    // this many cells across the (u, v) unit square
+    Uv(T, T),
+}
+
+pub struct Checker<T> {
+    even: Rc<dyn Texture<T>>,
+    odd: Rc<dyn Texture<T>>,
+    space: CheckerSpace<T>,
+}
+
+impl<T> Checker<T> {
+    pub fn solid(even: Rc<dyn Texture<T>>, odd: Rc<dyn Texture<T>>, scale: T) -> Self {
+        Self {
+            even,
+            odd,
+            space: CheckerSpace::Solid(scale),
+        }
+    }
+
+    pub fn uv(even: Rc<dyn Texture<T>>, odd: Rc<dyn Texture<T>>, cells: (T, T)) -> Self {
+        Self {
+            even,
+            odd,
+            space: CheckerSpace::Uv(cells.0, cells.1),
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Texture<T> for Checker<T> {
+    fn value(&self, u: T, v: T, p: &Vector3<T>) -> Vector3<T> {
+        let sum = match self.space {
+            CheckerSpace::Solid(scale) => {
+                (p.x / scale).floor() + (p.y / scale).floor() + (p.z / scale).floor()
+            }
+            CheckerSpace::Uv(cells_u, cells_v) => (u * cells_u).floor() + (v * cells_v).floor(),
+        };
+        let two = T::from(2.0).unwrap();
+        if sum - (sum / two).floor() * two == T::zero() {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// what happens to texture coordinates outside [0, 1]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    Clamp,
+}
+
+impl WrapMode {
+    fn texel(self, i: isize, size: usize) -> usize {
+        let size = size as isize;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::MirroredRepeat => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+            WrapMode::Clamp => i.max(0).min(size - 1),
+        };
+        i as usize
+    }
+}
+
+// linear rgb texels, bilinearly filtered. v = 0 is the bottom row of the image
+pub struct ImageTexture<T> {
+    width: usize,
+    height: usize,
+    texels: Vec<Vector3<T>>,
+    wrap_u: WrapMode,
+    wrap_v: WrapMode,
+}
+
+// the srgb transfer function, 8-bit images are assumed to be stored in srgb
+fn srgb_to_linear(c: u8) -> f64 {
+    let c = f64::from(c) / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl<T: cgmath::BaseFloat> ImageTexture<T> {
+    // texels are stored row by row from the top of the image
+    pub fn new(width: usize, height: usize, texels: Vec<Vector3<T>>) -> Self {
+        assert_eq!(
+            width * height,
+            texels.len(),
+            "image size doesn't match its texels"
+        );
+        assert!(width > 0 && height > 0, "empty image");
+        Self {
+            width,
+            height,
+            texels,
+            wrap_u: WrapMode::Repeat,
+            wrap_v: WrapMode::Repeat,
+        }
+    }
+
+    // anything the image crate can decode, png and jpeg in particular
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.to_rgb();
+        let (width, height) = image.dimensions();
+        let texels = image
+            .pixels()
+            .map(|p| {
+                vec3(
+                    T::from(srgb_to_linear(p[0])).unwrap(),
+                    T::from(srgb_to_linear(p[1])).unwrap(),
+                    T::from(srgb_to_linear(p[2])).unwrap(),
+                )
+            })
+            .collect();
+        Ok(Self::new(width as usize, height as usize, texels))
+    }
+
+    pub fn set_wrap(&mut self, wrap_u: WrapMode, wrap_v: WrapMode) {
+        self.wrap_u = wrap_u;
+        self.wrap_v = wrap_v;
+    }
+
+    fn texel(&self, x: isize, y: isize) -> Vector3<T> {
+        let x = self.wrap_u.texel(x, self.width);
+        let y = self.wrap_v.texel(y, self.height);
+        self.texels[x + self.width * (self.height - 1 - y)]
+    }
+}
+
+impl<T: cgmath::BaseFloat> Texture<T> for ImageTexture<T> {
+    fn value(&self, u: T, v: T, _p: &Vector3<T>) -> Vector3<T> {
+        // texel centers sit at half integer coordinates
+        let half = T::from(0.5).unwrap();
+        let x = u * T::from(self.width).unwrap() - half;
+        let y = v * T::from(self.height).unwrap() - half;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0.to_isize().unwrap_or(0), y0.to_isize().unwrap_or(0));
+        let lerp = |a: Vector3<T>, b: Vector3<T>, t: T| a + (b - a) * t;
+        lerp(
+            lerp(self.texel(x0, y0), self.texel(x0 + 1, y0), fx),
+            lerp(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), fx),
+            fy,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(c: f64) -> Rc<dyn Texture<f64>> {
+        Rc::new(SolidColor::new(vec3(c, c, c)))
+    }
+
+    fn close(a: Vector3<f64>, b: Vector3<f64>) -> bool {
+        (a - b).x.abs() < 1e-12 && (a - b).y.abs() < 1e-12 && (a - b).z.abs() < 1e-12
+    }
+
+    #[test]
+    fn checkers_alternate_across_cells() {
+        let origin = vec3(0.0, 0.0, 0.0);
+        let uv = Checker::uv(solid(0.0), solid(1.0), (4.0, 2.0));
+        assert_eq!(uv.value(0.1, 0.1, &origin).x, 0.0);
+        assert_eq!(uv.value(0.3, 0.1, &origin).x, 1.0);
+        assert_eq!(uv.value(0.3, 0.6, &origin).x, 0.0);
+        assert_eq!(uv.value(0.1, 0.6, &origin).x, 1.0);
+
+        // cells below zero keep alternating rather than mirroring
+        let world = Checker::solid(solid(0.0), solid(1.0), 0.5);
+        let at = |x, y, z| world.value(0.0, 0.0, &vec3(x, y, z)).x;
+        assert_eq!(at(0.25, 0.25, 0.25), 0.0);
+        assert_eq!(at(-0.25, 0.25, 0.25), 1.0);
+        assert_eq!(at(-0.75, 0.25, 0.25), 0.0);
+        assert_eq!(at(-0.25, -0.25, 0.25), 0.0);
+        assert_eq!(at(-0.25, -0.25, -0.25), 1.0);
+        assert_eq!(at(0.75, 1.25, 0.75), 0.0);
+    }
+
+    #[test]
+    fn wrap_modes_fold_texel_indices() {
+        let wrapped = |mode: WrapMode| (-5..9).map(|i| mode.texel(i, 3)).collect::<Vec<_>>();
+        assert_eq!(
+            wrapped(WrapMode::Repeat),
+            vec![1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2]
+        );
+        assert_eq!(
+            wrapped(WrapMode::MirroredRepeat),
+            vec![1, 2, 2, 1, 0, 0, 1, 2, 2, 1, 0, 0, 1, 2]
+        );
+        assert_eq!(
+            wrapped(WrapMode::Clamp),
+            vec![0, 0, 0, 0, 0, 0, 1, 2, 2, 2, 2, 2, 2, 2]
+        );
+    }
+
+    #[test]
+    fn bilinear_filtering_between_texel_centers() {
+        let (red, green) = (vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+        let (blue, white) = (vec3(0.0, 0.0, 1.0), vec3(1.0, 1.0, 1.0));
+        let mut image = ImageTexture::new(2, 2, vec![red, green, blue, white]);
+        let p = vec3(0.0, 0.0, 0.0);
+
+        // texel centers give back the texel, with v = 0 at the bottom
+        assert!(close(image.value(0.25, 0.75, &p), red));
+        assert!(close(image.value(0.75, 0.75, &p), green));
+        assert!(close(image.value(0.25, 0.25, &p), blue));
+        assert!(close(image.value(0.75, 0.25, &p), white));
+        // edges between texels are halfway
+        assert!(close(image.value(0.5, 0.25, &p), (blue + white) * 0.5));
+        assert!(close(
+            image.value(0.5, 0.5, &p),
+            (red + green + blue + white) * 0.25
+        ));
+        assert!(close(
+            image.value(0.375, 0.25, &p),
+            blue * 0.75 + white * 0.25
+        ));
+
+        // the image's own edges blend with the far side when repeating, not when clamped
+        assert!(close(image.value(0.0, 0.25, &p), (blue + white) * 0.5));
+        image.set_wrap(WrapMode::Clamp, WrapMode::Clamp);
+        assert!(close(image.value(0.0, 0.25, &p), blue));
+        assert!(close(image.value(-3.0, 7.0, &p), red));
+        image.set_wrap(WrapMode::MirroredRepeat, WrapMode::Repeat);
+        assert!(close(image.value(1.25, 0.25, &p), white));
+    }
+
+    #[test]
+    fn srgb_decodes_to_linear() {
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        // the linear toe below 0.04045
+        assert!((srgb_to_linear(10) - 10.0 / 255.0 / 12.92).abs() < 1e-12);
+        assert!((srgb_to_linear(128) - 0.215_860_5).abs() < 1e-6);
+        assert!((srgb_to_linear(188) - 0.502_886_5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,313 @@
+use super::csg::{Solid, Span};
+use super::cylinder::sort_crossings;
+use super::plane::turns;
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::{vec3, InnerSpace, Vector2, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+fn is_zero<T: cgmath::BaseFloat>(x: T) -> bool {
+    x.abs() < T::epsilon().sqrt() * T::from(0.1).unwrap()
+}
+
+fn c<T: cgmath::BaseFloat>(x: f64) -> T {
+    T::from(x).unwrap()
+}
+
+// real roots of x^2 + b x + c
+fn solve_normed_quadratic<T: cgmath::BaseFloat>(b: T, c0: T, roots: &mut Vec<T>) {
+    let p = b / c(2.0);
+    let d = p * p - c0;
+    if is_zero(d) {
+        roots.push(-p);
+    } else if d > T::zero() {
+        roots.push(d.sqrt() - p);
+        roots.push(-d.sqrt() - p);
+    }
+}
+
+// real roots of x^3 + a x^2 + b x + c, found with Cardano's method
+fn solve_normed_cubic<T: cgmath::BaseFloat>(a: T, b: T, c0: T) -> Vec<T> {
+    let sq_a = a * a;
+    let p = (-sq_a / c(3.0) + b) / c(3.0);
+    let q = (c::<T>(2.0 / 27.0) * a * sq_a - a * b / c(3.0) + c0) / c(2.0);
+    let cb_p = p * p * p;
+    let d = q * q + cb_p;
+    let mut roots = Vec::with_capacity(3);
+    if is_zero(d) {
+        if is_zero(q) {
+            roots.push(T::zero());
+        } else {
+            let u = (-q).cbrt();
+            roots.push(u * c(2.0));
+            roots.push(-u);
+        }
+    } else if d < T::zero() {
+        let phi = (-q / (-cb_p).sqrt()).max(-T::one()).min(T::one()).acos() / c(3.0);
+        let t = (-p).sqrt() * c(2.0);
+        let third = c::<T>(std::f64::consts::PI / 3.0);
+        roots.push(t * phi.cos());
+        roots.push(-t * (phi + third).cos());
+        roots.push(-t * (phi - third).cos());
+    } else {
+        let sqrt_d = d.sqrt();
+        roots.push((sqrt_d - q).cbrt() - (sqrt_d + q).cbrt());
+    }
+    let sub = a / c(3.0);
+    roots.iter().map(|root| *root - sub).collect()
+}
+
+// real roots of x^4 + a x^3 + b x^2 + c x + d using Ferrari's resolvent cubic
+pub(crate) fn solve_normed_quartic<T: cgmath::BaseFloat>(a: T, b: T, c0: T, d: T) -> Vec<T> {
+    let sq_a = a * a;
+    let p = c::<T>(-3.0 / 8.0) * sq_a + b;
+    let q = c::<T>(1.0 / 8.0) * sq_a * a - a * b / c(2.0) + c0;
+    let r = c::<T>(-3.0 / 256.0) * sq_a * sq_a + sq_a * b / c(16.0) - a * c0 / c(4.0) + d;
+    let mut roots = Vec::with_capacity(4);
+    if is_zero(r) {
+        // x (x^3 + p x + q) = 0
+        roots = solve_normed_cubic(T::zero(), p, q);
+        roots.push(T::zero());
+    } else {
+        let z = solve_normed_cubic(-p / c(2.0), -r, r * p / c(2.0) - q * q / c(8.0))[0];
+        let u = z * z - r;
+        let v = z * c(2.0) - p;
+        let root = |x: T| {
+            if is_zero(x) {
+                Some(T::zero())
+            } else if x > T::zero() {
+                Some(x.sqrt())
+            } else {
+                None
+            }
+        };
+        if let (Some(u), Some(v)) = (root(u), root(v)) {
+            let v = if q < T::zero() { -v } else { v };
+            solve_normed_quadratic(v, z - u, &mut roots);
+            solve_normed_quadratic(-v, z + u, &mut roots);
+        }
+    }
+    let sub = a / c(4.0);
+    roots.iter().map(|root| *root - sub).collect()
+}
+
+// a ring around `center` in the xz plane, `major` from the center to the middle of the tube
+// and `minor` the tube's radius. u goes around the ring, v around the tube starting outside
+pub struct Torus<T> {
+    center: Vector3<T>,
+    major: T,
+    minor: T,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T> Torus<T> {
+    pub fn new(center: Vector3<T>, major: T, minor: T, material: Rc<dyn Material<T>>) -> Self {
+        Self {
+            center,
+            major,
+            minor,
+            material,
+        }
+    }
+}
+
+impl<T: cgmath::BaseFloat> Torus<T> {
+    // every crossing of the surface along the line of the ray, in order
+    fn roots(&self, r: &Ray<T>) -> Vec<T> {
+        // the quartic is badly conditioned far from the torus, so solve it for a unit
+        // direction starting from the ray's closest approach to the center
+        let length = r.direction().magnitude();
+        let d = r.direction() / length;
+        let shift = -(r.origin() - self.center).dot(d);
+        let o = r.origin() - self.center + d * shift;
+        if o.magnitude() > self.major + self.minor {
+            return Vec::new();
+        }
+
+        let (r2, big_r2) = (self.minor * self.minor, self.major * self.major);
+        let e = o.magnitude2() + big_r2 - r2;
+        let f = o.dot(d);
+        let four_r2 = big_r2 * c(4.0);
+        let coefficients = [
+            e * e - four_r2 * (o.x * o.x + o.z * o.z),
+            f * e * c(4.0) - four_r2 * (o.x * d.x + o.z * d.z) * c(2.0),
+            f * f * c(4.0) + e * c(2.0) - four_r2 * (d.x * d.x + d.z * d.z),
+            f * c(4.0),
+        ];
+        let quartic = |s: T| {
+            (((s + coefficients[3]) * s + coefficients[2]) * s + coefficients[1]) * s
+                + coefficients[0]
+        };
+        let derivative = |s: T| {
+            ((s * c(4.0) + coefficients[3] * c(3.0)) * s + coefficients[2] * c(2.0)) * s
+                + coefficients[1]
+        };
+
+        let mut roots: Vec<T> = solve_normed_quartic(
+            coefficients[3],
+            coefficients[2],
+            coefficients[1],
+            coefficients[0],
+        )
+        .into_iter()
+        .map(|mut s| {
+            // a couple of newton steps clean up the closed form's rounding
+            for _ in 0..2 {
+                let slope = derivative(s);
+                if slope != T::zero() {
+                    s = s - quartic(s) / slope;
+                }
+            }
+            (s + shift) / length
+        })
+        .collect();
+        sort_crossings(&mut roots, |s| *s);
+        roots
+    }
+
+    fn hit_record(&self, r: &Ray<T>, temp: T) -> HitRecord<T> {
+        let p = r.point_at_parameter(temp);
+        let local = p - self.center;
+        let ring = vec3(local.x, T::zero(), local.z);
+        let ring = if ring.magnitude2() > T::zero() {
+            ring.normalize() * self.major
+        } else {
+            vec3(self.major, T::zero(), T::zero())
+        };
+        let normal = (local - ring) / self.minor;
+        let outward = (local - ring).dot(ring) / self.major;
+        let mut rec = HitRecord::new(r, temp, p, normal, Rc::clone(&self.material));
+        rec.set_uv(Vector2::new(
+            turns(local.z, local.x),
+            turns(local.y, outward),
+        ));
+        // around the tube is the normal turned a quarter towards +y
+        let dir = ring / self.major;
+        rec.set_tangents(
+            vec3(-local.z, T::zero(), local.x),
+            vec3(T::zero(), T::one(), T::zero()) * normal.dot(dir) - dir * normal.y,
+        );
+        rec
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Torus<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        self.roots(r)
+            .into_iter()
+            .find(|temp| *temp > t.start && *temp < t.end)
+            .map(|temp| self.hit_record(r, temp))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        let outer = self.major.abs() + self.minor.abs();
+        let extent = vec3(outer, self.minor.abs(), outer);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+impl<T: cgmath::BaseFloat> Solid<T> for Torus<T> {
+    // a grazing ray can report a double root once, so an odd crossing count is dropped
+    fn spans(&self, r: &Ray<T>) -> Vec<Span<T>> {
+        let roots = self.roots(r);
+        if roots.len() % 2 == 1 {
+            return Vec::new();
+        }
+        roots
+            .chunks(2)
+            .map(|pair| Span {
+                enter: self.hit_record(r, pair[0]),
+                exit: self.hit_record(r, pair[1]),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use std::f64::consts::PI;
+
+    fn torus() -> Torus<f64> {
+        Torus::new(
+            vec3(0.5, 1.0, -2.0),
+            2.0,
+            0.5,
+            Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))),
+        )
+    }
+
+    fn surface(u: f64, v: f64) -> (Vector3<f64>, Vector3<f64>) {
+        let (phi, theta) = (u * 2.0 * PI, v * 2.0 * PI);
+        let around = vec3(phi.cos(), 0.0, phi.sin());
+        let normal = around * theta.cos() + vec3(0.0, theta.sin(), 0.0);
+        (vec3(0.5, 1.0, -2.0) + around * 2.0 + normal * 0.5, normal)
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x + 2)(x - 3)(x + 0.5)
+        let mut roots = solve_normed_quartic(-1.5f64, -6.0, 3.5, 3.0);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip(&[-2.0, -0.5, 1.0, 3.0]) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        // x^4 + 1 has no real roots
+        assert!(solve_normed_quartic(0.0f64, 0.0, 0.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn hits_sampled_points() {
+        let torus = torus();
+        for i in 0..8 {
+            for j in 0..8 {
+                let u = (f64::from(i) + 0.25) / 8.0;
+                let v = (f64::from(j) + 0.25) / 8.0;
+                let (target, normal) = surface(u, v);
+                // shooting along the normal can still hit the other side of the ring first
+                // on the inner half, so only check the nearest hit from right above the point
+                let r = Ray::new(target + normal * 0.1, -normal * 2.0);
+                let rec = torus.hit(&r, 0.0..f64::MAX).unwrap();
+                assert!((rec.get_t() - 0.05).abs() < 1e-9);
+                assert!((rec.get_p() - target).magnitude() < 1e-9);
+                assert!((rec.get_normal() - normal).magnitude() < 1e-9);
+                assert!((rec.get_uv() - Vector2::new(u, v)).magnitude() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn hits_from_far_away() {
+        let torus = torus();
+        let (target, normal) = surface(0.1, 0.0);
+        let r = Ray::new(target + normal * 1000.0, -normal);
+        let rec = torus.hit(&r, 0.0..f64::MAX).unwrap();
+        assert!((rec.get_p() - target).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn misses_through_the_hole() {
+        let torus = torus();
+        let r = Ray::new(vec3(0.5, 10.0, -2.0), vec3(0.0, -1.0, 0.0));
+        assert!(torus.hit(&r, 0.0..f64::MAX).is_none());
+    }
+
+    #[test]
+    fn rays_through_the_center_cross_the_tube_twice() {
+        let shape = torus();
+        let r = Ray::new(vec3(-5.0, 1.0, -2.0), vec3(1.0, 0.0, 0.0));
+        let spans = shape.spans(&r);
+        assert_eq!(spans.len(), 2);
+        for (span, (enter, exit)) in spans.iter().zip(&[(3.0, 4.0), (7.0, 8.0)]) {
+            assert!((span.enter.get_t() - enter).abs() < 1e-9);
+            assert!((span.exit.get_t() - exit).abs() < 1e-9);
+        }
+        // from the center the nearest crossing is the inside of the ring
+        let out = Ray::new(vec3(0.5, 1.0, -2.0), vec3(0.0, 0.0, 1.0));
+        let rec = shape.hit(&out, 0.0..f64::MAX).unwrap();
+        assert!((rec.get_t() - 1.5).abs() < 1e-9);
+        assert!((rec.get_normal() - vec3(0.0, 0.0, -1.0)).magnitude() < 1e-9);
+    }
+}
@@ -0,0 +1,184 @@
+use super::{Aabb, HitRecord, HitTable, Material, Ray};
+use cgmath::{vec2, InnerSpace, Vector2, Vector3};
+use std::ops::Range;
+use std::rc::Rc;
+
+// Möller-Trumbore, returns (t, u, v) where u and v weight the second and third vertex
+pub(crate) fn intersect<T: cgmath::BaseFloat>(
+    vertices: [&Vector3<T>; 3],
+    r: &Ray<T>,
+    t: &Range<T>,
+) -> Option<(T, T, T)> {
+    let e1 = vertices[1] - vertices[0];
+    let e2 = vertices[2] - vertices[0];
+    let p = r.direction().cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < T::epsilon() {
+        return None;
+    }
+    let inv_det = T::one() / det;
+    let s = r.origin() - vertices[0];
+    let u = s.dot(p) * inv_det;
+    if u < T::zero() || u > T::one() {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = r.direction().dot(q) * inv_det;
+    if v < T::zero() || u + v > T::one() {
+        return None;
+    }
+    let temp = e2.dot(q) * inv_det;
+    if temp < t.end && temp > t.start {
+        Some((temp, u, v))
+    } else {
+        None
+    }
+}
+
+pub(crate) fn interpolate<T: cgmath::BaseFloat>(
+    attributes: [&Vector3<T>; 3],
+    u: T,
+    v: T,
+) -> Vector3<T> {
+    attributes[0] * (T::one() - u - v) + attributes[1] * u + attributes[2] * v
+}
+
+// uv comes from the per-vertex `uvs` when there are any, otherwise it's the barycentric (u, v)
+pub(crate) fn hit_record<T: cgmath::BaseFloat>(
+    vertices: [&Vector3<T>; 3],
+    normals: Option<[&Vector3<T>; 3]>,
+    uvs: Option<[&Vector2<T>; 3]>,
+    r: &Ray<T>,
+    (t, u, v): (T, T, T),
+    material: &Rc<dyn Material<T>>,
+) -> HitRecord<T> {
+    let e1 = vertices[1] - vertices[0];
+    let e2 = vertices[2] - vertices[0];
+    let normal = match normals {
+        Some(normals) => interpolate(normals, u, v).normalize(),
+        None => e1.cross(e2).normalize(),
+    };
+    let mut rec = HitRecord::new(r, t, r.point_at_parameter(t), normal, Rc::clone(material));
+    rec.set_barycentric(Some(vec2(u, v)));
+    match uvs {
+        Some(uvs) => {
+            rec.set_uv(uvs[0] * (T::one() - u - v) + uvs[1] * u + uvs[2] * v);
+            // solve e1 = du1 dpdu + dv1 dpdv and e2 = du2 dpdu + dv2 dpdv
+            let (d1, d2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+            let det = d1.x * d2.y - d1.y * d2.x;
+            if det != T::zero() {
+                rec.set_tangents((e1 * d2.y - e2 * d1.y) / det, (e2 * d1.x - e1 * d2.x) / det);
+            } else {
+                rec.set_tangents(e1, e2);
+            }
+        }
+        None => {
+            rec.set_uv(vec2(u, v));
+            rec.set_tangents(e1, e2);
+        }
+    }
+    rec
+}
+
+pub struct Triangle<T> {
+    vertices: [Vector3<T>; 3],
+    normals: Option<[Vector3<T>; 3]>,
+    material: Rc<dyn Material<T>>,
+}
+
+impl<T> Triangle<T> {
+    pub fn new(vertices: [Vector3<T>; 3], material: Rc<dyn Material<T>>) -> Self {
+        Self {
+            vertices,
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(
+        vertices: [Vector3<T>; 3],
+        normals: [Vector3<T>; 3],
+        material: Rc<dyn Material<T>>,
+    ) -> Self {
+        Self {
+            vertices,
+            normals: Some(normals),
+            material,
+        }
+    }
+
+    pub fn vertices(&self) -> &[Vector3<T>; 3] {
+        &self.vertices
+    }
+}
+
+impl<T: cgmath::BaseFloat> HitTable<T> for Triangle<T> {
+    fn hit(&self, r: &Ray<T>, t: Range<T>) -> Option<HitRecord<T>> {
+        let [a, b, c] = &self.vertices;
+        let hit = intersect([a, b, c], r, &t)?;
+        let normals = self.normals.as_ref().map(|[na, nb, nc]| [na, nb, nc]);
+        Some(hit_record([a, b, c], normals, None, r, hit, &self.material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb<T>> {
+        Aabb::from_points(self.vertices.iter().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+    use cgmath::vec3;
+
+    fn material() -> Rc<dyn Material<f64>> {
+        Rc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))
+    }
+
+    fn vertices() -> [Vector3<f64>; 3] {
+        [
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+        ]
+    }
+
+    fn down_at(x: f64, y: f64) -> Ray<f64> {
+        Ray::new(vec3(x, y, 1.0), vec3(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn triangle_hits_inside_and_misses_outside() {
+        let triangle = Triangle::new(vertices(), material());
+        let hit = triangle.hit(&down_at(0.2, 0.3), 0.001..10.0).unwrap();
+        assert!((hit.get_t() - 1.0).abs() < 1e-12);
+        assert_eq!(*hit.get_normal(), vec3(0.0, 0.0, 1.0));
+        assert!(triangle.hit(&down_at(0.6, 0.6), 0.001..10.0).is_none());
+        assert!(triangle.hit(&down_at(-0.1, 0.3), 0.001..10.0).is_none());
+        assert!(triangle.hit(&down_at(0.2, 0.3), 0.001..0.5).is_none());
+    }
+
+    #[test]
+    fn barycentrics_weight_the_second_and_third_vertex() {
+        let triangle = Triangle::new(vertices(), material());
+        let hit = triangle.hit(&down_at(0.2, 0.3), 0.001..10.0).unwrap();
+        let barycentric = hit.get_barycentric().unwrap();
+        assert!((barycentric.x - 0.2).abs() < 1e-12);
+        assert!((barycentric.y - 0.3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn smooth_normals_are_interpolated() {
+        let normals = [
+            vec3(0.0, 0.0, 1.0),
+            vec3(1.0, 0.0, 1.0).normalize(),
+            vec3(0.0, 1.0, 1.0).normalize(),
+        ];
+        let triangle = Triangle::with_normals(vertices(), normals, material());
+        let hit = triangle.hit(&down_at(0.2, 0.3), 0.001..10.0).unwrap();
+        let expected = (normals[0] * 0.5 + normals[1] * 0.2 + normals[2] * 0.3).normalize();
+        assert!((hit.get_normal() - expected).magnitude() < 1e-12);
+        let corner = triangle.hit(&down_at(0.0, 0.0), 0.001..10.0).unwrap();
+        assert!((corner.get_normal() - normals[0]).magnitude() < 1e-12);
+    }
+}